@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+/// Región del código fuente de la que proviene un nodo.
+/// Las líneas y columnas empiezan en 1, como en los errores de `pest`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+impl From<pest::Span<'_>> for Span {
+    fn from(span: pest::Span<'_>) -> Self {
+        let (line, col) = span.start_pos().line_col();
+        let (end_line, end_col) = span.end_pos().line_col();
+        Span {
+            start: span.start(),
+            end: span.end(),
+            line,
+            col,
+            end_line,
+            end_col,
+        }
+    }
+}
+
+impl Span {
+    /// Región que va desde el inicio de `self` hasta el final de `other`.
+    pub fn join(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            line: self.line,
+            col: self.col,
+            end: other.end,
+            end_line: other.end_line,
+            end_col: other.end_col,
+        }
+    }
+}
+
+/// Secuencia de instrucciones. Se comparte entre el programa y los marcos
+/// de ejecución del intérprete, por lo que clonarla es barato. Usa `Arc` para
+/// que los intérpretes puedan enviarse a otros hilos.
+pub type Block = Arc<[Stmt]>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub body: Block,
+    pub funciones: Vec<Funcion>,
+}
+
+/// Definición de una función. Solo puede aparecer en el nivel superior del
+/// programa y puede llamarse desde cualquier parte, incluso antes de su
+/// definición.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Funcion {
+    pub nombre: String,
+    pub params: Vec<String>,
+    pub cuerpo: Block,
+    pub span: Span,
+}
+
+/// Llamada a una función, ya sea como instrucción o dentro de una expresión.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Llamada {
+    pub nombre: String,
+    pub args: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Decl {
+        name: String,
+        value: Expr,
+    },
+    Asig {
+        name: String,
+        value: Expr,
+    },
+    Si {
+        cond: Expr,
+        bloque: Block,
+        otro: Option<Block>,
+    },
+    Mientras {
+        cond: Expr,
+        bloque: Block,
+    },
+    /// Ciclo con inicialización, condición y actualización. La inicialización
+    /// y la actualización son declaraciones o asignaciones; se comparten con los
+    /// marcos de ejecución del intérprete.
+    Para {
+        init: Arc<Stmt>,
+        cond: Expr,
+        update: Arc<Stmt>,
+        bloque: Block,
+    },
+    /// Ejecuta `bloque` tantas veces como indique `veces`, que se evalúa una
+    /// sola vez al entrar.
+    Repite {
+        veces: Expr,
+        bloque: Block,
+    },
+    Llamada(Llamada),
+    /// Termina la función en curso, con un valor si se usa dentro de una
+    /// expresión.
+    Regresa(Option<Expr>),
+    /// Sale del ciclo más cercano.
+    Rompe,
+    /// Pasa a la siguiente vuelta del ciclo más cercano.
+    Continua,
+    Gira(DirGiro),
+    Avanza,
+    Dispara(Arma),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirGiro {
+    Izquierda,
+    Derecha,
+}
+
+/// Arma con la que dispara un tanque. `dispara;` usa el arma chica.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arma {
+    Chico,
+    Grande,
+}
+
+/// Expresión de cualquier tipo. Los tipos se revisan durante la ejecución: las
+/// operaciones aritméticas y las comparaciones de orden esperan números, y los
+/// operadores lógicos y las condiciones esperan valores lógicos.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Int(isize),
+    Bool(bool),
+    Var(String),
+    Llamada(Llamada),
+    BinOp {
+        op: BinOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    UnOp {
+        op: UnOp,
+        expr: Box<Expr>,
+    },
+    Comp {
+        op: CompOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    LogicOp {
+        op: LogicOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Suma,
+    Resta,
+    Mult,
+    Div,
+    Modulo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Negativo,
+    Abs,
+    No,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompOp {
+    Men,
+    MenIg,
+    May,
+    MayIg,
+    Ig,
+    NoIg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogicOp {
+    And,
+    Or,
+}
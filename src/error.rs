@@ -1,6 +1,29 @@
-#[derive(Debug)]
+use crate::ast::Span;
+use crate::tablero::Casilla;
+use crate::tank_status::GRID_DIMMENSIONS;
+use crate::valor::Tipo;
+
+#[derive(Debug, Clone)]
 pub enum ErrorInterprete {
     VarNoDecl(String),
+    DivisionEntreCero,
+    Desbordamiento,
+    LimiteExcedido,
+    FuncionNoDecl(String),
+    NumArgumentos {
+        nombre: String,
+        esperados: usize,
+        recibidos: usize,
+    },
+    /// Una función usada dentro de una expresión terminó sin regresar un valor.
+    SinValor(String),
+    ProfundidadExcedida(usize),
+    /// Una operación o instrucción recibió un valor del tipo equivocado, por
+    /// ejemplo `1 + verdadero` o `si(x)` con `x` numérica.
+    TipoIncorrecto {
+        esperado: Tipo,
+        encontrado: Tipo,
+    },
 }
 
 impl std::fmt::Display for ErrorInterprete {
@@ -9,8 +32,308 @@ impl std::fmt::Display for ErrorInterprete {
             ErrorInterprete::VarNoDecl(var) => {
                 f.write_str(&format!("Variable no declarada previamente: {}", &var))
             }
+            ErrorInterprete::DivisionEntreCero => f.write_str("División entre cero"),
+            ErrorInterprete::Desbordamiento => {
+                f.write_str("El resultado de la operación es demasiado grande")
+            }
+            ErrorInterprete::LimiteExcedido => {
+                f.write_str("El programa excedió el límite de instrucciones")
+            }
+            ErrorInterprete::FuncionNoDecl(nombre) => {
+                write!(f, "Función no definida: {}", nombre)
+            }
+            ErrorInterprete::NumArgumentos {
+                nombre,
+                esperados,
+                recibidos,
+            } => write!(
+                f,
+                "La función {} recibe {}, pero se le pasaron {}",
+                nombre,
+                argumentos(*esperados),
+                recibidos
+            ),
+            ErrorInterprete::SinValor(nombre) => {
+                write!(f, "La función {} terminó sin regresar un valor", nombre)
+            }
+            ErrorInterprete::ProfundidadExcedida(max) => {
+                write!(f, "Se excedió el máximo de {} llamadas anidadas", max)
+            }
+            ErrorInterprete::TipoIncorrecto {
+                esperado,
+                encontrado,
+            } => write!(
+                f,
+                "Se esperaba {}, pero se encontró {}",
+                esperado, encontrado
+            ),
         }
     }
 }
 
 impl std::error::Error for ErrorInterprete {}
+
+/// "1 argumento", "2 argumentos", ...
+pub(crate) fn argumentos(n: usize) -> String {
+    if n == 1 {
+        "1 argumento".to_string()
+    } else {
+        format!("{} argumentos", n)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TipoBloque {
+    Si,
+    Mientras,
+    Para,
+    Repite,
+    /// Cuerpo de una función; el marco apunta a la llamada.
+    Funcion,
+}
+
+/// Bloque `si`, `mientras`, `para`, `repite` o llamada a función que encierra
+/// a la instrucción donde ocurrió un error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarcoBloque {
+    pub tipo: TipoBloque,
+    /// Región de la instrucción que abrió el bloque.
+    pub span: Span,
+}
+
+/// Error durante la ejecución de un programa, con la región de la instrucción o
+/// expresión que falló y los bloques que la encierran, del más interno al más
+/// externo.
+#[derive(Debug, Clone)]
+pub struct ErrorEjecucion {
+    pub error: ErrorInterprete,
+    pub span: Span,
+    pub traza: Vec<MarcoBloque>,
+}
+
+impl ErrorEjecucion {
+    pub fn new(error: ErrorInterprete, span: Span) -> Self {
+        ErrorEjecucion {
+            error,
+            span,
+            traza: vec![],
+        }
+    }
+}
+
+/// Largo máximo de un grupo de bloques que se repite en la traza, como una
+/// llamada recursiva dentro de un `si`.
+const MAX_GRUPO_REPETIDO: usize = 8;
+
+/// Largo del grupo que más bloques cubre al repetirse seguido al inicio de
+/// `traza`, y cuántas veces aparece. Dos bloques se repiten si son del mismo
+/// tipo y están en la misma línea. Regresa `(1, 1)` si nada se repite.
+fn repeticiones(traza: &[MarcoBloque]) -> (usize, usize) {
+    let mut mejor = (1, 1);
+    for largo in 1..=MAX_GRUPO_REPETIDO.min(traza.len()) {
+        let grupo = &traza[..largo];
+        let veces = traza
+            .chunks_exact(largo)
+            .take_while(|otro| {
+                otro.iter()
+                    .zip(grupo)
+                    .all(|(a, b)| a.tipo == b.tipo && a.span.line == b.span.line)
+            })
+            .count();
+        if veces > 1 && largo * veces > mejor.0 * mejor.1 {
+            mejor = (largo, veces);
+        }
+    }
+    mejor
+}
+
+impl std::fmt::Display for ErrorEjecucion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "error en la línea {}, columna {}: {}",
+            self.span.line, self.span.col, self.error
+        )?;
+        // A recursive call leaves the same blocks many times in a row, so
+        // each repeated group is shown once
+        let mut resto = &self.traza[..];
+        while !resto.is_empty() {
+            let (largo, veces) = repeticiones(resto);
+            for marco in &resto[..largo] {
+                let bloque = match marco.tipo {
+                    TipoBloque::Si => "`si`",
+                    TipoBloque::Mientras => "`mientras`",
+                    TipoBloque::Para => "`para`",
+                    TipoBloque::Repite => "`repite`",
+                    TipoBloque::Funcion => "la llamada",
+                };
+                write!(
+                    f,
+                    "\n  dentro de {} en la línea {}",
+                    bloque, marco.span.line
+                )?;
+            }
+            if veces > 1 && largo == 1 {
+                write!(f, " (repetido {} veces)", veces)?;
+            } else if veces > 1 {
+                write!(
+                    f,
+                    "\n  (los {} bloques anteriores se repiten {} veces)",
+                    largo, veces
+                )?;
+            }
+            resto = &resto[largo * veces..];
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ErrorEjecucion {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Error de sintaxis de un programa, con la región donde ocurrió y lo que se
+/// esperaba encontrar en ese punto.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorCompilacion {
+    pub span: Span,
+    /// Construcciones esperadas, ya descritas para el estudiante.
+    pub esperado: Vec<String>,
+    /// Texto encontrado en lugar de lo esperado, o `None` al final del programa.
+    pub encontrado: Option<String>,
+    pub mensaje: String,
+    /// Línea del código fuente donde empieza el error.
+    pub linea: String,
+}
+
+impl std::fmt::Display for ErrorCompilacion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let num_linea = self.span.line.to_string();
+        let margen = " ".repeat(num_linea.len());
+        let ancho = if self.span.end_line == self.span.line {
+            self.span.end_col.saturating_sub(self.span.col).max(1)
+        } else {
+            1
+        };
+        writeln!(f, "error: {}", self.mensaje)?;
+        writeln!(
+            f,
+            "{}--> línea {}, columna {}",
+            margen, self.span.line, self.span.col
+        )?;
+        writeln!(f, "{} |", margen)?;
+        writeln!(f, "{} | {}", num_linea, self.linea)?;
+        write!(
+            f,
+            "{} | {}{}",
+            margen,
+            " ".repeat(self.span.col.saturating_sub(1)),
+            "^".repeat(ancho)
+        )
+    }
+}
+
+impl std::error::Error for ErrorCompilacion {}
+
+/// Error en el texto de un tablero, ver `Tablero`. Las líneas y columnas
+/// empiezan en 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorTablero {
+    /// El carácter no corresponde a ninguna casilla.
+    Caracter {
+        linea: usize,
+        columna: usize,
+        caracter: char,
+    },
+    /// El texto tiene más filas o columnas que el tablero.
+    FueraDelTablero { linea: usize, columna: usize },
+}
+
+impl ErrorTablero {
+    /// Línea y columna del texto donde está el error.
+    pub fn posicion(&self) -> (usize, usize) {
+        match *self {
+            ErrorTablero::Caracter { linea, columna, .. }
+            | ErrorTablero::FueraDelTablero { linea, columna } => (linea, columna),
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorTablero {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (linea, columna) = self.posicion();
+        match self {
+            ErrorTablero::Caracter { caracter, .. } => write!(
+                f,
+                "`{}` no es una casilla; usa `.`, `#`, `c` o `~`",
+                caracter
+            )?,
+            ErrorTablero::FueraDelTablero { .. } => write!(
+                f,
+                "el tablero es de {} x {} casillas",
+                GRID_DIMMENSIONS, GRID_DIMMENSIONS
+            )?,
+        }
+        write!(f, " (línea {}, columna {})", linea, columna)
+    }
+}
+
+impl std::error::Error for ErrorTablero {}
+
+/// Error al agregar un tanque a la arena, ver `Arena::add_tank`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorArena {
+    /// El programa del tanque no compila.
+    Compilacion(Box<ErrorCompilacion>),
+    /// La casilla inicial está fuera del tablero.
+    FueraDelTablero { pos: (usize, usize) },
+    /// Otro tanque ya empieza o está en la casilla inicial.
+    CasillaOcupada { pos: (usize, usize), tanque: usize },
+    /// La casilla inicial tiene un obstáculo que no deja pasar a los tanques.
+    CasillaBloqueada {
+        pos: (usize, usize),
+        casilla: Casilla,
+    },
+}
+
+impl From<Box<ErrorCompilacion>> for ErrorArena {
+    fn from(e: Box<ErrorCompilacion>) -> Self {
+        ErrorArena::Compilacion(e)
+    }
+}
+
+impl std::fmt::Display for ErrorArena {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorArena::Compilacion(e) => e.fmt(f),
+            ErrorArena::FueraDelTablero { pos } => write!(
+                f,
+                "la casilla {:?} está fuera del tablero de {} x {} casillas",
+                pos, GRID_DIMMENSIONS, GRID_DIMMENSIONS
+            ),
+            ErrorArena::CasillaOcupada { pos, tanque } => {
+                write!(f, "la casilla {:?} ya es del tanque {}", pos, tanque)
+            }
+            ErrorArena::CasillaBloqueada { pos, casilla } => {
+                let obstaculo = match casilla {
+                    Casilla::Muro => "un muro",
+                    Casilla::Caja => "una caja",
+                    Casilla::Agua => "agua",
+                    Casilla::Libre => "un obstáculo",
+                };
+                write!(f, "la casilla {:?} tiene {}", pos, obstaculo)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ErrorArena {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ErrorArena::Compilacion(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
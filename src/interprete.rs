@@ -1,345 +1,1000 @@
-use crate::error::ErrorInterprete;
-use crate::parser::*;
+use crate::ast::*;
+use crate::error::{ErrorCompilacion, ErrorEjecucion, ErrorInterprete, MarcoBloque, TipoBloque};
+use crate::parser::compile;
 use crate::scope::Scope;
-use crate::tank_status::{Position, TankDirection, TankStatus, GRID_DIMMENSIONS};
-use pest::error::{Error, LineColLocation};
-use pest::iterators::{Pair, Pairs};
-use pest::prec_climber::*;
-use pest::Parser;
+use crate::tank_status::{TankDirection, TankStatus};
+use crate::tracer::Tracer;
+use crate::valor::{Tipo, Valor};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 const RADAR: &str = "radar";
+const MUNICION_CHICA: &str = "municion_chica";
+const MUNICION_GRANDE: &str = "municion_grande";
+const CHOQUE: &str = "choque";
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-pub enum ExecutionContext<'a> {
+/// Variables que el intérprete mantiene al día con el estado del tanque y que
+/// el programa no puede declarar ni asignar.
+pub(crate) const SOLO_LECTURA: &[&str] = &[RADAR, MUNICION_CHICA, MUNICION_GRANDE, CHOQUE];
+
+/// Valores de las variables de solo lectura para `status`.
+fn sensores(status: &TankStatus) -> [(&'static str, Valor); 3] {
+    let balas = |balas: usize| Valor::Entero(balas.try_into().unwrap_or(isize::MAX));
+    [
+        (MUNICION_CHICA, balas(status.ammo_small())),
+        (MUNICION_GRANDE, balas(status.ammo_big())),
+        (CHOQUE, Valor::Booleano(status.crashed())),
+    ]
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExecutionContext {
     Block,
     IfBlock,
-    While(Pair<'a, Rule>),
+    While(Box<Expr>),
+    /// Ciclo `para`. El marco no tiene instrucciones propias: la fase indica
+    /// qué parte del ciclo se ejecuta al llegar al final del bloque.
+    For {
+        init: Arc<Stmt>,
+        cond: Box<Expr>,
+        update: Arc<Stmt>,
+        bloque: Block,
+        fase: FasePara,
+    },
+    /// Cuerpo de un `repite`, con el número de vueltas que faltan después de
+    /// la actual.
+    Repeat {
+        restantes: usize,
+    },
+    /// Cuerpo de una función. `con_valor` indica si la llamada está dentro de
+    /// una expresión que espera el valor regresado.
+    Call {
+        nombre: String,
+        con_valor: bool,
+    },
+}
+
+/// Parte de un ciclo `para` que sigue al llegar al final de su marco.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FasePara {
+    Init,
+    Cond,
+    /// El cuerpo está en ejecución, con su propio scope dentro del scope de la
+    /// variable del ciclo.
+    Cuerpo,
+    Update,
+}
+
+impl ExecutionContext {
+    fn tipo_bloque(&self) -> Option<TipoBloque> {
+        match self {
+            ExecutionContext::Block => None,
+            ExecutionContext::IfBlock => Some(TipoBloque::Si),
+            ExecutionContext::While(_) => Some(TipoBloque::Mientras),
+            ExecutionContext::For { .. } => Some(TipoBloque::Para),
+            ExecutionContext::Repeat { .. } => Some(TipoBloque::Repite),
+            ExecutionContext::Call { .. } => Some(TipoBloque::Funcion),
+        }
+    }
+
+    fn es_ciclo(&self) -> bool {
+        matches!(
+            self,
+            ExecutionContext::While(_)
+                | ExecutionContext::For { .. }
+                | ExecutionContext::Repeat { .. }
+        )
+    }
+}
+
+/// Acción del tanque realizada en un paso.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accion {
+    Avanza,
+    Gira(DirGiro),
+    Dispara(Arma),
+    /// El tanque intentó disparar sin munición del arma; pierde el turno.
+    SinMunicion(Arma),
 }
 
-#[derive(Debug)]
-pub struct Interpreter<'a> {
-    exec_stack: Vec<(Pairs<'a, Rule>, ExecutionContext<'a>)>,
+/// Lo que ocurrió en un paso de la ejecución.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepResult {
+    /// El tanque realizó una acción; es el único resultado que gasta un turno
+    /// del juego.
+    Accion(Accion),
+    /// Se declaró o asignó una variable.
+    Asignacion { nombre: String, valor: Valor },
+    /// Se evaluó la condición de un `si` o de un `mientras`.
+    Condicion { valor: bool },
+    /// Se evaluó el número de vueltas de un `repite`. Las vueltas siguientes
+    /// empiezan sin gastar un paso.
+    Repite { veces: usize },
+    /// Se llamó a una función; su primera instrucción se ejecuta en el
+    /// siguiente paso.
+    Llamada { nombre: String },
+    /// Una función terminó con `regresa`.
+    Regreso { valor: Option<Valor> },
+    /// Se ejecutó `rompe` o `continua`.
+    Salto,
+    /// El paso agotó su combustible antes de completarse; la ejecución continúa
+    /// en el siguiente.
+    Pausa,
+    /// El programa terminó.
+    Fin,
+}
+
+/// Límites al trabajo que hace el intérprete, medido en unidades de
+/// combustible. Cada instrucción, evaluación de condición y salida de bloque
+/// consume una unidad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limites {
+    /// Combustible disponible en cada llamada a `step_inst`. Al agotarse, el paso
+    /// termina sin error y la ejecución continúa en el siguiente, así un ciclo
+    /// vacío infinito nunca bloquea al anfitrión. Siempre se permite al menos una
+    /// unidad.
+    pub por_paso: usize,
+    /// Combustible para toda la ejecución. Al agotarse, `step_inst` regresa
+    /// `ErrorInterprete::LimiteExcedido`.
+    pub total: Option<usize>,
+    /// Máximo de llamadas a funciones anidadas. Al excederse, `step_inst`
+    /// regresa `ErrorInterprete::ProfundidadExcedida`.
+    pub profundidad: usize,
+}
+
+impl Default for Limites {
+    fn default() -> Self {
+        Limites {
+            por_paso: 1000,
+            total: None,
+            profundidad: 100,
+        }
+    }
+}
+
+/// Bloque en ejecución junto con la posición de la siguiente instrucción y la
+/// región de la instrucción que lo abrió.
+#[derive(Debug, Clone)]
+struct Frame {
+    block: Block,
+    pc: usize,
+    ctx: ExecutionContext,
+    span: Span,
+    // Valores ya calculados de la expresión interrumpida por una llamada, ver
+    // `Evaluador`
+    memoria: Vec<Valor>,
+}
+
+impl Frame {
+    fn new(block: Block, ctx: ExecutionContext, span: Span) -> Self {
+        Frame {
+            block,
+            pc: 0,
+            ctx,
+            span,
+            memoria: vec![],
+        }
+    }
+}
+
+/// Intérprete de un programa de `tank`. Es dueño de su programa compilado, por
+/// lo que no depende del texto fuente y puede guardarse o moverse entre hilos.
+pub struct Interpreter {
+    exec_stack: Vec<Frame>,
     scope: Scope,
+    funciones: HashMap<String, Arc<Funcion>>,
+    // Llamadas a funciones en curso
+    profundidad: usize,
+    tracer: Option<Box<dyn Tracer + Send>>,
+    limites: Limites,
+    // Combustible restante en el paso actual
+    combustible_paso: usize,
+    consumo: usize,
+}
+
+impl std::fmt::Debug for Interpreter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("exec_stack", &self.exec_stack)
+            .field("scope", &self.scope)
+            .field("funciones", &self.funciones)
+            .field("tracer", &self.tracer.is_some())
+            .field("limites", &self.limites)
+            .field("consumo", &self.consumo)
+            .finish()
+    }
 }
 
-impl<'a> Interpreter<'a> {
-    pub fn new(prog: &'a str) -> Result<Self, LineColLocation> {
-        let pairs = ParserTanques::parse(Rule::prog, prog).map_err(|e| e.line_col)?;
+impl Interpreter {
+    pub fn new(prog: &str) -> Result<Self, Box<ErrorCompilacion>> {
+        let program = compile(prog)?;
+        Ok(Self::from_program(program))
+    }
+
+    /// Crea un intérprete a partir de un programa ya compilado. Los bloques del
+    /// programa se comparten, así que varios intérpretes pueden ejecutar el mismo
+    /// programa sin volver a compilarlo.
+    pub fn from_program(program: Program) -> Self {
         let mut scope = Scope::new();
-        scope.define_new_scope_var(RADAR, 0);
-        Ok(Self {
-            exec_stack: vec![(pairs, ExecutionContext::Block)],
+        scope.define_new_scope_var(RADAR, Valor::Entero(0));
+        for (nombre, valor) in sensores(&TankStatus::default()) {
+            scope.define_new_scope_var(nombre, valor);
+        }
+        Self {
+            exec_stack: vec![Frame::new(
+                program.body,
+                ExecutionContext::Block,
+                Span::default(),
+            )],
             scope,
-        })
+            funciones: program
+                .funciones
+                .into_iter()
+                .map(|funcion| (funcion.nombre.clone(), Arc::new(funcion)))
+                .collect(),
+            profundidad: 0,
+            tracer: None,
+            limites: Limites::default(),
+            combustible_paso: 0,
+            consumo: 0,
+        }
+    }
+
+    pub fn set_limites(&mut self, limites: Limites) {
+        self.limites = limites;
     }
 
-    pub fn get_var_value(&self, varname: &str) -> Option<isize> {
+    pub fn limites(&self) -> Limites {
+        self.limites
+    }
+
+    /// Combustible consumido desde el inicio de la ejecución.
+    pub fn consumo(&self) -> usize {
+        self.consumo
+    }
+
+    /// Instala un observador que recibe los eventos de la ejecución.
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer + Send>) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Quita el observador instalado, si lo hay, y lo regresa.
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer + Send>> {
+        self.tracer.take()
+    }
+
+    pub fn get_var_value(&self, varname: &str) -> Option<Valor> {
         self.scope.get_var_value(varname)
     }
 
-    fn parse_node(
+    /// Variables visibles en el punto actual de la ejecución.
+    pub fn variables(&self) -> Vec<(String, Valor)> {
+        self.scope.visible_vars()
+    }
+
+    /// Ejecuta `stmt` sobre `status`. Las acciones del tanque modifican `status`;
+    /// `si` y `mientras` solo evalúan su condición y, si corresponde, entran a su
+    /// bloque, cuya primera instrucción se ejecuta en el siguiente paso. Si una
+    /// expresión llama a una función, la instrucción se interrumpe antes de
+    /// modificar algo y se vuelve a ejecutar cuando la función regresa.
+    fn exec_stmt(
         &mut self,
-        pair: Pair<'a, Rule>,
-        current_status: &TankStatus,
-    ) -> Result<TankStatus, ErrorInterprete> {
-        let (current_line, _current_col) = pair.as_span().end_pos().line_col();
-        println!("Descending");
-        dbg!(&pair.as_rule());
-        match pair.as_rule() {
-            Rule::inst => {
-                let inst_inner = pair.into_inner().next().unwrap();
-                self.parse_node(inst_inner, current_status)
-            }
-            Rule::bloque => {
-                let bloque_inner = pair.into_inner().next().unwrap();
-                self.parse_node(bloque_inner, current_status)
+        stmt: &Stmt,
+        status: &mut TankStatus,
+    ) -> Result<StepResult, Interrupcion> {
+        let reanudada = !self.exec_stack.last().unwrap().memoria.is_empty();
+        if let (false, Some(tracer)) = (reanudada, &mut self.tracer) {
+            tracer.on_instruction(stmt);
+        }
+        status.set_current_interpreter_line(stmt.span.end_line);
+        match &stmt.kind {
+            StmtKind::Decl { name, value } => {
+                let valor = self.evalua(|ev| ev.eval(value))?;
+                self.define_var(name, valor);
+                Ok(StepResult::Asignacion {
+                    nombre: name.clone(),
+                    valor,
+                })
             }
-            Rule::decl => {
-                let mut decl_pairs = pair.into_inner();
-                let var_name = decl_pairs.next().unwrap().as_str();
-                let expr = decl_pairs.next().unwrap();
-                let valor = eval(expr.into_inner(), &self.scope)?;
-                self.scope.define_new_scope_var(var_name, valor);
-                let mut new_status = *current_status;
-                new_status.set_current_interpreter_line(current_line);
-                Ok(new_status)
+            StmtKind::Asig { name, value } => {
+                let valor = self.evalua(|ev| ev.eval(value))?;
+                if !self.set_var(name, valor) {
+                    return Err(ErrorEjecucion::new(
+                        ErrorInterprete::VarNoDecl(name.clone()),
+                        stmt.span,
+                    )
+                    .into());
+                }
+                Ok(StepResult::Asignacion {
+                    nombre: name.clone(),
+                    valor,
+                })
             }
-            Rule::asig => {
-                let mut asig_pairs = pair.into_inner();
-                let var_name = asig_pairs.next().unwrap().as_str();
-                let expr = asig_pairs.next().unwrap();
-                let valor = eval(expr.into_inner(), &self.scope)?;
-                let found = self.scope.set_scope_var(var_name, valor);
-                if !found {
-                    Err(ErrorInterprete::VarNoDecl(var_name.into()))
+            StmtKind::Si { cond, bloque, otro } => {
+                status.set_current_interpreter_line(cond.span.end_line);
+                let expr_val = self.evalua(|ev| ev.eval_bool(cond))?;
+                let bloque = if expr_val {
+                    Some(bloque)
                 } else {
-                    let mut new_status = *current_status;
-                    new_status.set_current_interpreter_line(current_line);
-                    Ok(new_status)
+                    otro.as_ref()
+                };
+                if let Some(bloque) = bloque {
+                    self.push_block(bloque.clone(), ExecutionContext::IfBlock, stmt.span);
                 }
+                Ok(StepResult::Condicion { valor: expr_val })
             }
-            Rule::bloque_si => {
-                let mut pairs = pair.into_inner();
-                let expr_logic = pairs.next().unwrap();
-                let expr_val = eval_logic(expr_logic.into_inner(), &self.scope)?;
-                let bloque_principal = pairs.next();
-                let bloque_else = pairs.next();
+            StmtKind::Mientras { cond, bloque } => {
+                status.set_current_interpreter_line(cond.span.end_line);
+                let expr_val = self.evalua(|ev| ev.eval_bool(cond))?;
                 if expr_val {
-                    self.scope.add();
-                    let instrucciones = bloque_principal.unwrap().into_inner();
-                    self.exec_stack
-                        .push((instrucciones, ExecutionContext::IfBlock));
-                    self.step_inst(current_status)
-                } else {
-                    if let Some(bloque) = bloque_else {
-                        self.scope.add();
-                        let instrucciones = bloque.into_inner();
-                        self.exec_stack
-                            .push((instrucciones, ExecutionContext::IfBlock));
-                        self.step_inst(current_status)
-                    } else {
-                        let mut new_status = *current_status;
-                        new_status.set_current_interpreter_line(current_line);
-                        Ok(new_status)
-                    }
+                    self.push_block(
+                        bloque.clone(),
+                        ExecutionContext::While(Box::new(cond.clone())),
+                        stmt.span,
+                    );
                 }
+                Ok(StepResult::Condicion { valor: expr_val })
             }
-            Rule::bloque_mientras => {
-                let mut pairs = pair.into_inner();
-                let expr_logic = pairs.next().unwrap();
-                let expr_logic_clone = expr_logic.clone();
-                let expr_val = eval_logic(expr_logic.into_inner(), &self.scope)?;
-                if expr_val {
-                    self.scope.add();
-                    let instrucciones = pairs.next().unwrap().into_inner();
-                    let instrucciones_clone = instrucciones.clone();
-                    self.exec_stack.push((
-                        instrucciones,
-                        ExecutionContext::While(expr_logic_clone.clone()),
-                    ));
-                    self.exec_stack.push((
-                        instrucciones_clone,
-                        ExecutionContext::While(expr_logic_clone),
-                    ));
-                    self.step_inst(current_status)
-                } else {
-                    let mut new_status = *current_status;
-                    new_status.set_current_interpreter_line(current_line);
-                    Ok(new_status)
+            StmtKind::Para {
+                init,
+                cond,
+                update,
+                bloque,
+            } => {
+                // The frame has no statements of its own, see `exec_para`
+                let ctx = ExecutionContext::For {
+                    init: init.clone(),
+                    cond: Box::new(cond.clone()),
+                    update: update.clone(),
+                    bloque: bloque.clone(),
+                    fase: FasePara::Init,
+                };
+                self.push_block(Block::from([]), ctx, stmt.span);
+                self.exec_para(status)
+            }
+            StmtKind::Repite { veces, bloque } => {
+                let veces = self.evalua(|ev| ev.eval_entero(veces))?;
+                // A negative count runs the block zero times
+                let veces = usize::try_from(veces).unwrap_or(0);
+                if veces > 0 {
+                    self.push_block(
+                        bloque.clone(),
+                        ExecutionContext::Repeat {
+                            restantes: veces - 1,
+                        },
+                        stmt.span,
+                    );
                 }
+                Ok(StepResult::Repite { veces })
+            }
+            StmtKind::Llamada(llamada) => {
+                let args = self.evalua(|ev| ev.eval_args(&llamada.args))?;
+                Ok(self.llama(&llamada.nombre, args, stmt.span, false)?)
+            }
+            StmtKind::Rompe => {
+                self.salta(true);
+                Ok(StepResult::Salto)
+            }
+            StmtKind::Continua => {
+                self.salta(false);
+                Ok(StepResult::Salto)
+            }
+            StmtKind::Regresa(value) => {
+                let valor = match value {
+                    Some(value) => Some(self.evalua(|ev| ev.eval(value))?),
+                    None => None,
+                };
+                self.regresa(valor, stmt.span)?;
+                Ok(StepResult::Regreso { valor })
             }
-            Rule::gira => {
-                let mut pairs = pair.into_inner();
-                // let dir = pairs.skip(1).next().unwrap();
-                let dir = pairs.next().unwrap();
-                let new_dir = match dir.as_str() {
-                    "izquierda" => match current_status.get_dir() {
+            StmtKind::Gira(dir) => {
+                let new_dir = match dir {
+                    DirGiro::Izquierda => match status.get_dir() {
                         TankDirection::North => TankDirection::West,
                         TankDirection::West => TankDirection::South,
                         TankDirection::South => TankDirection::East,
                         TankDirection::East => TankDirection::North,
                     },
-                    "derecha" => match current_status.get_dir() {
+                    DirGiro::Derecha => match status.get_dir() {
                         TankDirection::North => TankDirection::East,
                         TankDirection::West => TankDirection::North,
                         TankDirection::South => TankDirection::West,
                         TankDirection::East => TankDirection::South,
                     },
-                    _ => unreachable!(),
                 };
 
-                let mut new_status = *current_status;
-                new_status.set_dir(new_dir);
-                new_status.set_current_interpreter_line(current_line);
-                self.scope.set_scope_var(RADAR, new_status.calc_radar());
-                Ok(new_status)
+                status.set_dir(new_dir);
+                self.set_var(RADAR, Valor::Entero(status.calc_radar()));
+                Ok(StepResult::Accion(Accion::Gira(*dir)))
             }
-            Rule::avanza => {
-                let (old_i, old_j) = current_status.get_pos();
-                let (new_i, new_j) = match current_status.get_dir() {
-                    TankDirection::West => (old_i, old_j.saturating_sub(1)),
-                    TankDirection::North => (old_i.saturating_sub(1), old_j),
-                    TankDirection::East => (
-                        old_i,
-                        if old_j + 1 == GRID_DIMMENSIONS {
-                            old_j
-                        } else {
-                            old_j + 1
-                        },
-                    ),
-                    TankDirection::South => (
-                        if old_i + 1 == GRID_DIMMENSIONS {
-                            old_i
-                        } else {
-                            old_i + 1
-                        },
-                        old_j,
-                    ),
-                };
-
-                let mut new_status = *current_status;
-                new_status.set_pos(new_i, new_j);
-                new_status.set_current_interpreter_line(current_line);
-                self.scope.set_scope_var(RADAR, new_status.calc_radar());
-                Ok(new_status)
+            StmtKind::Avanza => {
+                // En el borde del tablero el tanque choca y no se mueve
+                match status.next_pos() {
+                    Some((i, j)) => {
+                        status.set_pos(i, j);
+                        status.set_crashed(false);
+                    }
+                    None => status.set_crashed(true),
+                }
+                self.set_var(RADAR, Valor::Entero(status.calc_radar()));
+                self.actualiza_sensores(status);
+                Ok(StepResult::Accion(Accion::Avanza))
             }
-            Rule::dispara => {
-                let mut new_status = *current_status;
-                new_status.set_shot(true);
-                new_status.set_current_interpreter_line(current_line);
-                Ok(new_status)
+            StmtKind::Dispara(arma) => {
+                if !status.spend_ammo(*arma) {
+                    return Ok(StepResult::Accion(Accion::SinMunicion(*arma)));
+                }
+                status.set_shot(true);
+                self.actualiza_sensores(status);
+                Ok(StepResult::Accion(Accion::Dispara(*arma)))
+            }
+        }
+    }
+
+    /// Ejecuta la inicialización o la actualización del `para` en ejecución,
+    /// según su fase, en el scope de la variable del ciclo.
+    fn exec_para(&mut self, status: &mut TankStatus) -> Result<StepResult, Interrupcion> {
+        let frame = self.exec_stack.last_mut().unwrap();
+        let stmt = match &frame.ctx {
+            ExecutionContext::For {
+                init,
+                fase: FasePara::Init,
+                ..
+            } => init.clone(),
+            ExecutionContext::For { update, .. } => update.clone(),
+            _ => unreachable!("exec_para fuera de un `para`"),
+        };
+        let resultado = self.exec_stmt(&stmt, status)?;
+        if let ExecutionContext::For { fase, .. } = &mut self.exec_stack.last_mut().unwrap().ctx {
+            *fase = FasePara::Cond;
+        }
+        Ok(resultado)
+    }
+
+    /// Ejecuta un paso del programa sobre `status` y regresa lo que ocurrió en él.
+    /// Un paso es exactamente una de estas cosas: una acción del tanque, una
+    /// declaración o asignación (incluidas la inicialización y la actualización
+    /// de un `para`), la evaluación de la condición de un `si`, un `mientras` o
+    /// un `para` (incluida la que se repite al final de cada vuelta), la del
+    /// número de vueltas de un `repite`, una llamada a función, un `regresa`, un
+    /// `rompe` o un `continua`. Salir de un bloque no cuenta como paso. Al
+    /// terminar el programa, este y todos los pasos siguientes regresan
+    /// `StepResult::Fin`.
+    pub fn step_inst(&mut self, status: &mut TankStatus) -> Result<StepResult, ErrorEjecucion> {
+        self.combustible_paso = self.limites.por_paso.max(1);
+        self.actualiza_sensores(status);
+        self.step(status).map_err(|mut e| {
+            e.traza = self.block_trace();
+            e
+        })
+    }
+
+    /// Bloques `si`, `mientras`, `para`, `repite` y llamadas en ejecución, del
+    /// más interno al más externo.
+    fn block_trace(&self) -> Vec<MarcoBloque> {
+        self.exec_stack
+            .iter()
+            .rev()
+            .filter_map(|frame| {
+                Some(MarcoBloque {
+                    tipo: frame.ctx.tipo_bloque()?,
+                    span: frame.span,
+                })
+            })
+            .collect()
+    }
+
+    /// Actualiza `radar` y las variables de solo lectura con `status`, después
+    /// de que algo fuera del programa cambió al tanque o a su alrededor. La
+    /// lectura del `radar` la da quien conoce el tablero, como la arena.
+    pub fn sensa(&mut self, status: &TankStatus, radar: isize) {
+        let radar = Valor::Entero(radar);
+        if self.scope.get_var_value(RADAR) != Some(radar) {
+            self.set_var(RADAR, radar);
+        }
+        self.actualiza_sensores(status);
+    }
+
+    /// Copia la munición y el choque del tanque a sus variables, que pueden
+    /// cambiar fuera del programa.
+    fn actualiza_sensores(&mut self, status: &TankStatus) {
+        for (nombre, valor) in sensores(status) {
+            if self.scope.get_var_value(nombre) != Some(valor) {
+                self.set_var(nombre, valor);
             }
-            Rule::EOI => Ok(*current_status),
-            _ => unreachable!(),
         }
     }
 
-    pub fn step_inst(
+    fn define_var(&mut self, name: &str, value: Valor) {
+        self.scope.define_new_scope_var(name, value);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.on_var_change(name, value);
+        }
+    }
+
+    fn set_var(&mut self, name: &str, value: Valor) -> bool {
+        let found = self.scope.set_scope_var(name, value);
+        if let (true, Some(tracer)) = (found, &mut self.tracer) {
+            tracer.on_var_change(name, value);
+        }
+        found
+    }
+
+    /// Evalúa con la memoria del bloque en ejecución, ver `Evaluador`.
+    fn evalua<T>(
         &mut self,
-        current_status: &TankStatus,
-    ) -> Result<TankStatus, ErrorInterprete> {
-        let (mut current_exec_block, ctx) = self.exec_stack.pop().unwrap();
-        if let Some(pair) = dbg!(current_exec_block.next()) {
-            self.exec_stack.push((current_exec_block, ctx));
-            self.parse_node(pair, current_status)
+        f: impl FnOnce(&mut Evaluador) -> Result<T, Interrupcion>,
+    ) -> Result<T, Interrupcion> {
+        let memoria = &mut self.exec_stack.last_mut().unwrap().memoria;
+        evalua(&self.scope, memoria, f)
+    }
+
+    /// Entra a un bloque `si`, `mientras`, `para`, `repite` o al cuerpo de una
+    /// función con un scope nuevo. Una función además abre un marco nuevo en el
+    /// scope.
+    fn push_block(&mut self, block: Block, ctx: ExecutionContext, span: Span) {
+        if let (Some(tipo), Some(tracer)) = (ctx.tipo_bloque(), &mut self.tracer) {
+            tracer.on_block_enter(tipo, span);
+        }
+        if let ExecutionContext::Call { .. } = ctx {
+            self.scope.add_frame();
+            self.profundidad += 1;
         } else {
-            // Reached the end of the iterator, check for condition
-            match ctx {
-                ExecutionContext::Block => {
-                    self.exec_stack.push((current_exec_block, ctx));
-                    Ok(*current_status)
+            self.scope.add();
+        }
+        self.exec_stack.push(Frame::new(block, ctx, span));
+    }
+
+    /// Sale del bloque en ejecución y de su scope.
+    fn pop_block(&mut self) {
+        if let Some(frame) = self.exec_stack.pop() {
+            match frame.ctx {
+                ExecutionContext::Call { .. } => {
+                    self.scope.drop_frame();
+                    self.profundidad -= 1;
                 }
-                ExecutionContext::IfBlock => {
+                ExecutionContext::For {
+                    fase: FasePara::Cuerpo,
+                    ..
+                } => {
+                    // Leave both the body and the loop variable scopes
+                    self.scope.drop();
                     self.scope.drop();
-                    self.step_inst(current_status)
                 }
-                ExecutionContext::While(p) => {
-                    let pair = p.clone();
-                    let pairs = pair.into_inner();
-                    let expr_val = dbg!(eval_logic(pairs, &self.scope))?;
+                _ => self.scope.drop(),
+            }
+            if let (Some(tipo), Some(tracer)) = (frame.ctx.tipo_bloque(), &mut self.tracer) {
+                tracer.on_block_exit(tipo, frame.span);
+            }
+        }
+    }
+
+    /// Entra al cuerpo de la función `nombre` con sus parámetros definidos.
+    /// `span` es la región de la llamada.
+    fn llama(
+        &mut self,
+        nombre: &str,
+        args: Vec<Valor>,
+        span: Span,
+        con_valor: bool,
+    ) -> Result<StepResult, ErrorEjecucion> {
+        let funcion = match self.funciones.get(nombre) {
+            Some(funcion) => funcion.clone(),
+            None => {
+                return Err(ErrorEjecucion::new(
+                    ErrorInterprete::FuncionNoDecl(nombre.to_string()),
+                    span,
+                ))
+            }
+        };
+        if funcion.params.len() != args.len() {
+            return Err(ErrorEjecucion::new(
+                ErrorInterprete::NumArgumentos {
+                    nombre: nombre.to_string(),
+                    esperados: funcion.params.len(),
+                    recibidos: args.len(),
+                },
+                span,
+            ));
+        }
+        if self.profundidad >= self.limites.profundidad {
+            return Err(ErrorEjecucion::new(
+                ErrorInterprete::ProfundidadExcedida(self.limites.profundidad),
+                span,
+            ));
+        }
+        let ctx = ExecutionContext::Call {
+            nombre: nombre.to_string(),
+            con_valor,
+        };
+        self.push_block(funcion.cuerpo.clone(), ctx, span);
+        for (param, valor) in funcion.params.iter().zip(args) {
+            self.define_var(param, valor);
+        }
+        Ok(StepResult::Llamada {
+            nombre: nombre.to_string(),
+        })
+    }
+
+    /// Termina la función en curso, saliendo de todos sus bloques, y entrega
+    /// `valor` a la expresión que la llamó. `span` es la región donde termina la
+    /// función, para reportar si se esperaba un valor. Fuera de una función
+    /// termina el programa.
+    fn regresa(&mut self, valor: Option<Valor>, span: Span) -> Result<(), ErrorEjecucion> {
+        loop {
+            if self.exec_stack.len() == 1 {
+                let frame = &mut self.exec_stack[0];
+                frame.pc = frame.block.len();
+                return Ok(());
+            }
+            let frame = self.exec_stack.last().unwrap();
+            if let ExecutionContext::Call { nombre, con_valor } = &frame.ctx {
+                let (nombre, con_valor) = (nombre.clone(), *con_valor);
+                self.pop_block();
+                if !con_valor {
+                    return Ok(());
+                }
+                let valor = valor
+                    .ok_or_else(|| ErrorEjecucion::new(ErrorInterprete::SinValor(nombre), span))?;
+                // The caller evaluates its expression again, now with this value
+                self.exec_stack.last_mut().unwrap().memoria.push(valor);
+                return Ok(());
+            }
+            self.pop_block();
+        }
+    }
+
+    /// Sale de los bloques hasta llegar al ciclo más cercano. Con `rompe` sale
+    /// también del ciclo; si no, salta al final de su cuerpo, donde sigue la
+    /// siguiente vuelta como si el cuerpo hubiera terminado.
+    fn salta(&mut self, rompe: bool) {
+        while let Some(frame) = self.exec_stack.last_mut() {
+            if frame.ctx.es_ciclo() {
+                if rompe {
+                    self.pop_block();
+                } else {
+                    frame.pc = frame.block.len();
+                }
+                return;
+            }
+            if let ExecutionContext::Block | ExecutionContext::Call { .. } = frame.ctx {
+                // The compiler only allows jumps inside loops
+                return;
+            }
+            self.pop_block();
+        }
+    }
+
+    /// Continúa la ejecución después de que la evaluación de una expresión se
+    /// interrumpió.
+    fn interrumpe(&mut self, interrupcion: Interrupcion) -> Result<StepResult, ErrorEjecucion> {
+        match interrupcion {
+            Interrupcion::Error(e) => Err(e),
+            Interrupcion::Llamada { nombre, args, span } => self.llama(&nombre, args, span, true),
+        }
+    }
+
+    /// Avanza la ejecución hasta completar un paso. Salir de bloques no termina
+    /// el paso, así que se itera sobre `exec_stack` en lugar de usar recursión:
+    /// el uso de la pila nativa no depende del anidamiento del programa.
+    fn step(&mut self, status: &mut TankStatus) -> Result<StepResult, ErrorEjecucion> {
+        loop {
+            let frame = self.exec_stack.last().unwrap();
+            let terminado = self.exec_stack.len() == 1 && frame.pc >= frame.block.len();
+            if terminado {
+                return Ok(StepResult::Fin);
+            }
+            if self.combustible_paso == 0 {
+                // Yield to the host, execution resumes here on the next step
+                return Ok(StepResult::Pausa);
+            }
+            if self
+                .limites
+                .total
+                .is_some_and(|total| self.consumo >= total)
+            {
+                let span = frame
+                    .block
+                    .get(frame.pc)
+                    .map_or(frame.span, |stmt| stmt.span);
+                return Err(ErrorEjecucion::new(ErrorInterprete::LimiteExcedido, span));
+            }
+            self.combustible_paso -= 1;
+            self.consumo += 1;
+
+            let frame = self.exec_stack.last_mut().unwrap();
+            if frame.pc < frame.block.len() {
+                // Blocks are reference counted, cloning them is cheap
+                let block = frame.block.clone();
+                let stmt = &block[frame.pc];
+                frame.pc += 1;
+                let profundidad = self.exec_stack.len();
+                return self.exec_stmt(stmt, status).or_else(|interrupcion| {
+                    // Run the statement again once the function returns. A
+                    // `para` already entered its frame, which resumes by itself
+                    let entro = self.exec_stack.len() > profundidad;
+                    if let (Interrupcion::Llamada { .. }, false) = (&interrupcion, entro) {
+                        self.exec_stack.last_mut().unwrap().pc -= 1;
+                    }
+                    self.interrumpe(interrupcion)
+                });
+            }
+            // Reached the end of the block, check for condition
+            match &mut frame.ctx {
+                ExecutionContext::Block => return Ok(StepResult::Fin),
+                ExecutionContext::IfBlock => self.pop_block(),
+                ExecutionContext::While(cond) => {
+                    // Leave the body scope before checking the condition, so
+                    // the body's variables can't hide the ones it uses. The
+                    // fresh scope is the next lap's, unless the condition is
+                    // being resumed after a function call
+                    if frame.memoria.is_empty() {
+                        self.scope.drop();
+                        self.scope.add();
+                    }
+                    status.set_current_interpreter_line(cond.span.end_line);
+                    let expr_val =
+                        match evalua(&self.scope, &mut frame.memoria, |ev| ev.eval_bool(cond)) {
+                            Ok(expr_val) => expr_val,
+                            Err(interrupcion) => return self.interrumpe(interrupcion),
+                        };
                     if !expr_val {
-                        // Loop ends, pop the cloned pairs object
-                        self.exec_stack.pop();
-                        self.step_inst(current_status)
+                        // Loop ends, leave its scope
+                        self.pop_block();
                     } else {
-                        // Loop  continues, push another pairs object to the stack
-                        let (pairs, ctx) = self.exec_stack.pop().unwrap();
-                        let pairs_clone = pairs.clone();
-                        let ctx_clone = ctx.clone();
-                        self.exec_stack.push((pairs, ctx));
-                        self.exec_stack.push((pairs_clone, ctx_clone));
-                        self.step_inst(current_status)
+                        // Loop continues, start the block again
+                        frame.pc = 0;
                     }
+                    return Ok(StepResult::Condicion { valor: expr_val });
+                }
+                ExecutionContext::For {
+                    fase: fase @ FasePara::Cuerpo,
+                    ..
+                } => {
+                    // Leave the body scope, the update runs in the loop scope
+                    *fase = FasePara::Update;
+                    frame.block = Block::from([]);
+                    self.scope.drop();
+                }
+                ExecutionContext::For {
+                    cond,
+                    bloque,
+                    fase: fase @ FasePara::Cond,
+                    ..
+                } => {
+                    status.set_current_interpreter_line(cond.span.end_line);
+                    let expr_val =
+                        match evalua(&self.scope, &mut frame.memoria, |ev| ev.eval_bool(cond)) {
+                            Ok(expr_val) => expr_val,
+                            Err(interrupcion) => return self.interrumpe(interrupcion),
+                        };
+                    if expr_val {
+                        *fase = FasePara::Cuerpo;
+                        frame.block = bloque.clone();
+                        frame.pc = 0;
+                        self.scope.add();
+                    } else {
+                        self.pop_block();
+                    }
+                    return Ok(StepResult::Condicion { valor: expr_val });
+                }
+                ExecutionContext::For { .. } => {
+                    return self
+                        .exec_para(status)
+                        .or_else(|interrupcion| self.interrumpe(interrupcion));
+                }
+                ExecutionContext::Repeat { restantes: 0 } => self.pop_block(),
+                ExecutionContext::Repeat { restantes } => {
+                    // Next lap, with a fresh scope
+                    *restantes -= 1;
+                    frame.pc = 0;
+                    self.scope.drop();
+                    self.scope.add();
+                }
+                ExecutionContext::Call { .. } => {
+                    // The function ended without `regresa`
+                    let span = frame.span;
+                    self.regresa(None, span)?;
                 }
             }
         }
     }
 }
 
-pub fn eval(expr: Pairs<Rule>, scope: &Scope) -> Result<isize, ErrorInterprete> {
-    let climber = PrecClimber::new(vec![
-        Operator::new(Rule::suma, Assoc::Left) | Operator::new(Rule::resta, Assoc::Left),
-        Operator::new(Rule::mult, Assoc::Left) | Operator::new(Rule::div, Assoc::Left),
-    ]);
-
-    let infix = |lhs: Result<isize, ErrorInterprete>,
-                 op: Pair<Rule>,
-                 rhs: Result<isize, ErrorInterprete>| {
-        match (lhs, rhs) {
-            (Ok(lhs), Ok(rhs)) => match op.as_rule() {
-                Rule::suma => Ok(lhs + rhs),
-                Rule::resta => Ok(lhs - rhs),
-                Rule::mult => Ok(lhs * rhs),
-                Rule::div => Ok(lhs / rhs),
-                _ => unreachable!(),
+/// Motivo por el que se detuvo la evaluación de una expresión.
+enum Interrupcion {
+    Error(ErrorEjecucion),
+    /// La expresión llama a una función cuyo valor todavía no se conoce.
+    Llamada {
+        nombre: String,
+        args: Vec<Valor>,
+        span: Span,
+    },
+}
+
+impl From<ErrorEjecucion> for Interrupcion {
+    fn from(e: ErrorEjecucion) -> Self {
+        Interrupcion::Error(e)
+    }
+}
+
+/// Evalúa expresiones. El cuerpo de una función se ejecuta paso a paso, así que
+/// una llamada no puede resolverse dentro de la evaluación: esta se interrumpe,
+/// el intérprete ejecuta la función y después evalúa la expresión completa otra
+/// vez. Las variables leídas y los valores regresados antes de la interrupción
+/// se guardan en `memoria` y se reutilizan en el mismo orden, así el resultado
+/// es el mismo que si la expresión se hubiera evaluado de una sola vez.
+struct Evaluador<'a> {
+    scope: &'a Scope,
+    memoria: &'a mut Vec<Valor>,
+    pos: usize,
+}
+
+/// Evalúa con `f` usando `memoria`, que se conserva solo si la evaluación se
+/// interrumpe por una llamada.
+fn evalua<T>(
+    scope: &Scope,
+    memoria: &mut Vec<Valor>,
+    f: impl FnOnce(&mut Evaluador) -> Result<T, Interrupcion>,
+) -> Result<T, Interrupcion> {
+    let resultado = f(&mut Evaluador {
+        scope,
+        memoria,
+        pos: 0,
+    });
+    if !matches!(resultado, Err(Interrupcion::Llamada { .. })) {
+        memoria.clear();
+    }
+    resultado
+}
+
+impl Evaluador<'_> {
+    /// Siguiente valor guardado en la memoria, si ya se calculó.
+    fn recuerda(&mut self) -> Option<Valor> {
+        let valor = self.memoria.get(self.pos).copied()?;
+        self.pos += 1;
+        Some(valor)
+    }
+
+    fn guarda(&mut self, valor: Valor) -> Valor {
+        self.memoria.push(valor);
+        self.pos += 1;
+        valor
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Valor, Interrupcion> {
+        match &expr.kind {
+            ExprKind::Int(value) => Ok(Valor::Entero(*value)),
+            ExprKind::Bool(value) => Ok(Valor::Booleano(*value)),
+            ExprKind::Var(name) => {
+                if let Some(value) = self.recuerda() {
+                    return Ok(value);
+                }
+                match self.scope.get_var_value(name) {
+                    Some(value) => Ok(self.guarda(value)),
+                    None => Err(ErrorEjecucion::new(
+                        ErrorInterprete::VarNoDecl(name.clone()),
+                        expr.span,
+                    )
+                    .into()),
+                }
+            }
+            ExprKind::Llamada(llamada) => {
+                let args = self.eval_args(&llamada.args)?;
+                self.recuerda().ok_or_else(|| Interrupcion::Llamada {
+                    nombre: llamada.nombre.clone(),
+                    args,
+                    span: expr.span,
+                })
+            }
+            ExprKind::BinOp { op, lhs, rhs } => {
+                let lhs = self.eval_entero(lhs)?;
+                let rhs = self.eval_entero(rhs)?;
+                let resultado = match op {
+                    BinOp::Suma => lhs.checked_add(rhs),
+                    BinOp::Resta => lhs.checked_sub(rhs),
+                    BinOp::Mult => lhs.checked_mul(rhs),
+                    BinOp::Div if rhs == 0 => {
+                        return Err(ErrorEjecucion::new(
+                            ErrorInterprete::DivisionEntreCero,
+                            expr.span,
+                        )
+                        .into())
+                    }
+                    BinOp::Div => lhs.checked_div(rhs),
+                    BinOp::Modulo if rhs == 0 => {
+                        return Err(ErrorEjecucion::new(
+                            ErrorInterprete::DivisionEntreCero,
+                            expr.span,
+                        )
+                        .into())
+                    }
+                    // El residuo tiene el signo del dividendo, igual que `/`
+                    // redondea hacia cero
+                    BinOp::Modulo => lhs.checked_rem(rhs),
+                };
+                resultado.map(Valor::Entero).ok_or_else(|| {
+                    ErrorEjecucion::new(ErrorInterprete::Desbordamiento, expr.span).into()
+                })
+            }
+            ExprKind::UnOp { op, expr: operando } => {
+                let resultado = match op {
+                    UnOp::No => return Ok(Valor::Booleano(!self.eval_bool(operando)?)),
+                    UnOp::Negativo => self.eval_entero(operando)?.checked_neg(),
+                    UnOp::Abs => self.eval_entero(operando)?.checked_abs(),
+                };
+                resultado.map(Valor::Entero).ok_or_else(|| {
+                    ErrorEjecucion::new(ErrorInterprete::Desbordamiento, expr.span).into()
+                })
+            }
+            ExprKind::Comp { op, lhs, rhs } => Ok(Valor::Booleano(match op {
+                // `==` y `!=` comparan valores del mismo tipo; las demás
+                // comparaciones, solo números
+                CompOp::Ig | CompOp::NoIg => {
+                    let lhs = self.eval(lhs)?;
+                    let rhs_valor = self.eval(rhs)?;
+                    if lhs.tipo() != rhs_valor.tipo() {
+                        return Err(tipo_incorrecto(lhs.tipo(), rhs_valor, rhs.span));
+                    }
+                    (lhs == rhs_valor) == (*op == CompOp::Ig)
+                }
+                CompOp::Men => self.eval_entero(lhs)? < self.eval_entero(rhs)?,
+                CompOp::MenIg => self.eval_entero(lhs)? <= self.eval_entero(rhs)?,
+                CompOp::May => self.eval_entero(lhs)? > self.eval_entero(rhs)?,
+                CompOp::MayIg => self.eval_entero(lhs)? >= self.eval_entero(rhs)?,
+            })),
+            // Corto circuito: el lado derecho sólo se evalúa si el izquierdo
+            // no decide el resultado, así que `x != 0 && 10 / x > 1` nunca
+            // divide entre cero ni llama a funciones de más.
+            ExprKind::LogicOp { op, lhs, rhs } => match (op, self.eval_bool(lhs)?) {
+                (LogicOp::Or, true) => Ok(Valor::Booleano(true)),
+                (LogicOp::And, false) => Ok(Valor::Booleano(false)),
+                _ => Ok(Valor::Booleano(self.eval_bool(rhs)?)),
             },
-            (e, Ok(_)) => e,
-            (Ok(_), e) => e,
-            (e, Err(_)) => e,
         }
-    };
+    }
 
-    let primary = |pair: Pair<Rule>| match pair.as_rule() {
-        Rule::expr_par => {
-            let expr_inner = pair.into_inner();
-            eval(expr_inner, scope)
+    /// Evalúa `expr`, que debe ser un número.
+    fn eval_entero(&mut self, expr: &Expr) -> Result<isize, Interrupcion> {
+        match self.eval(expr)? {
+            Valor::Entero(valor) => Ok(valor),
+            valor => Err(tipo_incorrecto(Tipo::Entero, valor, expr.span)),
         }
-        Rule::expr => eval(pair.into_inner(), scope),
-        Rule::int => Ok(pair.as_str().parse::<isize>().unwrap()),
-        Rule::nom_var => match scope.get_var_value(pair.as_str()) {
-            Some(value) => Ok(value),
-            None => Err(ErrorInterprete::VarNoDecl(pair.as_str().into())),
-        },
-        r => {
-            dbg!(r);
-            unreachable!()
+    }
+
+    /// Evalúa `expr`, que debe ser un valor lógico, como la condición de un
+    /// `si`.
+    fn eval_bool(&mut self, expr: &Expr) -> Result<bool, Interrupcion> {
+        match self.eval(expr)? {
+            Valor::Booleano(valor) => Ok(valor),
+            valor => Err(tipo_incorrecto(Tipo::Booleano, valor, expr.span)),
         }
-    };
+    }
 
-    climber.climb(expr, primary, infix)
+    fn eval_args(&mut self, args: &[Expr]) -> Result<Vec<Valor>, Interrupcion> {
+        args.iter().map(|arg| self.eval(arg)).collect()
+    }
 }
 
-pub fn eval_logic(expr: Pairs<Rule>, scope: &Scope) -> Result<bool, ErrorInterprete> {
-    let climber = PrecClimber::new(vec![
-        Operator::new(Rule::men, Assoc::Left)
-            | Operator::new(Rule::may, Assoc::Left)
-            | Operator::new(Rule::men_ig, Assoc::Left)
-            | Operator::new(Rule::may_ig, Assoc::Left)
-            | Operator::new(Rule::ig, Assoc::Left)
-            | Operator::new(Rule::no_ig, Assoc::Left),
-        Operator::new(Rule::or, Assoc::Left),
-        Operator::new(Rule::and, Assoc::Left),
-    ]);
-
-    let infix =
-        |lhs: Result<bool, ErrorInterprete>, op: Pair<Rule>, rhs: Result<bool, ErrorInterprete>| {
-            match (lhs, rhs) {
-                (Ok(lhs), Ok(rhs)) => match op.as_rule() {
-                    Rule::or => Ok(lhs || rhs),
-                    Rule::and => Ok(lhs && rhs),
-                    _ => unreachable!(),
-                },
-                (e, Ok(_)) => e,
-                (Ok(_), e) => e,
-                (e, Err(_)) => e,
-            }
-        };
+fn tipo_incorrecto(esperado: Tipo, valor: Valor, span: Span) -> Interrupcion {
+    ErrorEjecucion::new(
+        ErrorInterprete::TipoIncorrecto {
+            esperado,
+            encontrado: valor.tipo(),
+        },
+        span,
+    )
+    .into()
+}
 
-    let primary = |pair: Pair<Rule>| match pair.as_rule() {
-        Rule::expr_par_logic => {
-            let expr_inner = pair.into_inner();
-            eval_logic(expr_inner, scope)
+/// Evalúa una expresión fuera de la ejecución de un programa, por lo que no
+/// puede llamar a funciones.
+#[cfg(test)]
+pub fn eval(expr: &Expr, scope: &Scope) -> Result<Valor, ErrorEjecucion> {
+    evalua(scope, &mut vec![], |ev| ev.eval(expr)).map_err(|interrupcion| match interrupcion {
+        Interrupcion::Error(e) => e,
+        Interrupcion::Llamada { nombre, span, .. } => {
+            ErrorEjecucion::new(ErrorInterprete::FuncionNoDecl(nombre), span)
         }
-        Rule::expr_logic => eval_logic(pair.into_inner(), scope),
-        Rule::term_logic => eval_logic(pair.into_inner(), scope),
-        Rule::comp_logic => {
-            let mut pairs = pair.into_inner();
-            let lhs = {
-                let pairs = pairs.next().unwrap().into_inner();
-                eval(pairs, scope)
-            }?;
-            let op = match pairs.next().unwrap().as_rule() {
-                Rule::men => |lhs: isize, rhs: isize| lhs < rhs,
-                Rule::men_ig => |lhs: isize, rhs: isize| lhs <= rhs,
-                Rule::may => |lhs: isize, rhs: isize| lhs > rhs,
-                Rule::may_ig => |lhs: isize, rhs: isize| lhs >= rhs,
-                Rule::ig => |lhs: isize, rhs: isize| lhs == rhs,
-                Rule::no_ig => |lhs: isize, rhs: isize| lhs != rhs,
-                _ => unreachable!(),
-            };
-
-            let rhs = {
-                let pairs = pairs.next().unwrap().into_inner();
-                eval(pairs, scope)
-            }?;
-
-            Ok(op(lhs, rhs))
-        }
-        r => {
-            dbg!(r);
-            unreachable!()
-        }
-    };
-
-    climber.climb(expr, primary, infix)
+    })
 }
@@ -1,8 +1,21 @@
+pub mod ast;
 pub mod error;
 mod interprete;
+pub mod mundo;
 mod parser;
 mod scope;
+pub mod tablero;
 pub mod tank_status;
 mod test;
-pub use interprete::Interpreter;
-pub use pest::error::LineColLocation;
+pub mod tracer;
+pub mod valor;
+pub mod wasm;
+pub use ast::Program;
+pub use error::{ErrorArena, ErrorCompilacion, ErrorEjecucion, ErrorInterprete, ErrorTablero};
+pub use interprete::{Accion, Interpreter, Limites, StepResult};
+pub use mundo::Arena;
+pub use parser::compile;
+pub use tablero::{Casilla, Tablero};
+pub use tracer::Tracer;
+pub use valor::Valor;
+pub use wasm::{WasmArena, WasmInterpreter};
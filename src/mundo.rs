@@ -0,0 +1,365 @@
+use crate::ast::{Arma, Program};
+use crate::error::{ErrorArena, ErrorEjecucion};
+use crate::interprete::{Accion, Interpreter, StepResult};
+use crate::tablero::{Casilla, Tablero};
+use crate::tank_status::{next_pos, Position, TankStatus, GRID_DIMMENSIONS};
+use wasm_bindgen::prelude::*;
+
+/// Pasos que un tanque puede ejecutar en un turno sin realizar una acción. Al
+/// agotarse, el tanque pierde el turno y continúa donde se quedó en el
+/// siguiente; así un ciclo sin acciones no bloquea a los demás tanques.
+const PASOS_POR_TURNO: usize = 1000;
+
+/// Vida que quita un disparo de `arma` y casillas que recorre como máximo.
+fn potencia(arma: Arma) -> (usize, usize) {
+    match arma {
+        Arma::Chico => (10, GRID_DIMMENSIONS),
+        Arma::Grande => (30, 4),
+    }
+}
+
+/// Situación del programa de un tanque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[wasm_bindgen]
+pub enum EstadoTanque {
+    /// El programa sigue en ejecución.
+    Activo,
+    /// El programa terminó.
+    Terminado,
+    /// El programa se detuvo por un error, ver `Tanque::error`.
+    Error,
+    /// El tanque se quedó sin vida. Sus restos siguen ocupando la casilla y
+    /// no dejan pasar a otros tanques, pero los disparos pasan por encima.
+    Destruido,
+}
+
+/// Tanque de la arena junto con el intérprete de su programa.
+#[derive(Debug)]
+pub struct Tanque {
+    interprete: Interpreter,
+    status: TankStatus,
+    estado: EstadoTanque,
+    error: Option<ErrorEjecucion>,
+}
+
+impl Tanque {
+    pub fn status(&self) -> &TankStatus {
+        &self.status
+    }
+
+    pub fn estado(&self) -> EstadoTanque {
+        self.estado
+    }
+
+    /// Error que detuvo el programa, si lo hubo.
+    pub fn error(&self) -> Option<&ErrorEjecucion> {
+        self.error.as_ref()
+    }
+
+    /// Intérprete del programa, para consultar sus variables.
+    pub fn interprete(&self) -> &Interpreter {
+        &self.interprete
+    }
+
+    /// Ejecuta el programa hasta su siguiente acción, que ya quedó aplicada
+    /// sobre `status`, o hasta agotar `pasos`. `id` es el índice del tanque en
+    /// la arena.
+    fn juega(&mut self, id: usize, pasos: usize) -> Option<Evento> {
+        for _ in 0..pasos {
+            match self.interprete.step_inst(&mut self.status) {
+                Ok(StepResult::Accion(accion)) => {
+                    return Some(Evento::Accion { tanque: id, accion })
+                }
+                Ok(StepResult::Fin) => {
+                    self.estado = EstadoTanque::Terminado;
+                    return Some(Evento::Fin { tanque: id });
+                }
+                // The fuel ran out, the program resumes here next turn
+                Ok(StepResult::Pausa) => return None,
+                Ok(_) => {}
+                Err(e) => {
+                    self.estado = EstadoTanque::Error;
+                    self.error = Some(e);
+                    return Some(Evento::Error { tanque: id });
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Lo que ocurrió con un tanque durante un turno. `tanque` es su índice en la
+/// arena.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Evento {
+    /// El tanque realizó una acción.
+    Accion { tanque: usize, accion: Accion },
+    /// El disparo de `tanque` alcanzó a `objetivo`. Sigue al evento de la
+    /// acción `Dispara`; un disparo que llega al borde del tablero, a un muro o
+    /// al final de su alcance no produce este evento.
+    Impacto { tanque: usize, objetivo: usize },
+    /// El disparo de `tanque` destruyó la caja en la casilla `pos`, que quedó
+    /// libre. Sigue al evento de la acción `Dispara`.
+    CajaDestruida { tanque: usize, pos: (usize, usize) },
+    /// `tanque` intentó avanzar hacia el borde del tablero, hacia un obstáculo
+    /// o hacia la casilla de otro tanque y se quedó en su lugar. Sigue al
+    /// evento de la acción `Avanza`.
+    Choque { tanque: usize },
+    /// `tanque` se quedó sin vida y ya no juega. Sigue al evento `Impacto`
+    /// del disparo que lo destruyó.
+    Destruido { tanque: usize },
+    /// El programa del tanque terminó.
+    Fin { tanque: usize },
+    /// El programa del tanque se detuvo por un error.
+    Error { tanque: usize },
+}
+
+/// Tablero con todos los tanques de una partida. Cada tanque tiene su propio
+/// intérprete; en cada turno, los tanques activos realizan una acción cada
+/// uno, en el orden en que se agregaron.
+#[derive(Debug)]
+pub struct Arena {
+    tablero: Tablero,
+    tanques: Vec<Tanque>,
+    turno: usize,
+    pasos_por_turno: usize,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena {
+            tablero: Tablero::new(),
+            tanques: vec![],
+            turno: 0,
+            pasos_por_turno: PASOS_POR_TURNO,
+        }
+    }
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compila `prog` y agrega un tanque que lo ejecuta, empezando en
+    /// `status`. Regresa el índice del tanque. La casilla inicial debe estar
+    /// dentro del tablero, libre de obstáculos y de otros tanques.
+    pub fn add_tank(&mut self, prog: &str, status: TankStatus) -> Result<usize, ErrorArena> {
+        self.add_interpreter(Interpreter::new(prog)?, status)
+    }
+
+    /// Agrega un tanque con un programa ya compilado, que puede compartirse
+    /// entre varios tanques.
+    pub fn add_program(
+        &mut self,
+        program: Program,
+        status: TankStatus,
+    ) -> Result<usize, ErrorArena> {
+        self.add_interpreter(Interpreter::from_program(program), status)
+    }
+
+    /// Agrega un tanque con un intérprete ya configurado, por ejemplo con
+    /// límites o un observador.
+    pub fn add_interpreter(
+        &mut self,
+        interprete: Interpreter,
+        status: TankStatus,
+    ) -> Result<usize, ErrorArena> {
+        self.verifica_inicio(status.get_pos())?;
+        self.tanques.push(Tanque {
+            interprete,
+            status,
+            estado: EstadoTanque::Activo,
+            error: None,
+        });
+        Ok(self.tanques.len() - 1)
+    }
+
+    /// Revisa que un tanque nuevo pueda empezar en `pos`.
+    fn verifica_inicio(&self, pos: (usize, usize)) -> Result<(), ErrorArena> {
+        let casilla = self
+            .tablero
+            .get(pos)
+            .ok_or(ErrorArena::FueraDelTablero { pos })?;
+        if casilla.bloquea() {
+            return Err(ErrorArena::CasillaBloqueada { pos, casilla });
+        }
+        if let Some(tanque) = self
+            .tanques
+            .iter()
+            .position(|tanque| tanque.status.get_pos() == pos)
+        {
+            return Err(ErrorArena::CasillaOcupada { pos, tanque });
+        }
+        Ok(())
+    }
+
+    /// Cambia el terreno de la partida. Los tanques ya agregados no se mueven,
+    /// aunque queden sobre un obstáculo.
+    pub fn set_tablero(&mut self, tablero: Tablero) {
+        self.tablero = tablero;
+    }
+
+    pub fn tablero(&self) -> &Tablero {
+        &self.tablero
+    }
+
+    /// Cambia el contenido de una casilla del tablero. Regresa `false` si está
+    /// fuera del tablero.
+    pub fn set_casilla(&mut self, pos: (usize, usize), casilla: Casilla) -> bool {
+        self.tablero.set(pos, casilla)
+    }
+
+    pub fn tanks(&self) -> &[Tanque] {
+        &self.tanques
+    }
+
+    pub fn tank(&self, tanque: usize) -> Option<&Tanque> {
+        self.tanques.get(tanque)
+    }
+
+    /// Turnos jugados hasta ahora.
+    pub fn turn(&self) -> usize {
+        self.turno
+    }
+
+    /// Configura los pasos que un tanque puede ejecutar en un turno sin
+    /// realizar una acción. Siempre se permite al menos uno.
+    pub fn set_pasos_por_turno(&mut self, pasos: usize) {
+        self.pasos_por_turno = pasos.max(1);
+    }
+
+    /// La partida termina cuando ningún tanque sigue activo.
+    pub fn terminada(&self) -> bool {
+        self.tanques
+            .iter()
+            .all(|tanque| tanque.estado != EstadoTanque::Activo)
+    }
+
+    /// Juega un turno y regresa lo que hizo cada tanque. Un tanque que agota
+    /// sus pasos sin realizar una acción no produce ningún evento. Los
+    /// disparos se resuelven en cuanto ocurren, y las marcas de disparo del
+    /// turno anterior se borran al empezar. El `radar` de cada tanque se
+    /// actualiza con los obstáculos del tablero antes de que juegue y después
+    /// de resolver su acción; si un disparo destruye una caja, también el de
+    /// los demás tanques.
+    pub fn step_turn(&mut self) -> Vec<Evento> {
+        self.turno += 1;
+        for tanque in &mut self.tanques {
+            tanque.status.set_shot(false);
+            tanque.status.set_got_shot(false);
+        }
+
+        let mut eventos = vec![];
+        for id in 0..self.tanques.len() {
+            if self.tanques[id].estado != EstadoTanque::Activo {
+                continue;
+            }
+            self.sensa(id);
+            let tanque = &mut self.tanques[id];
+            let antes = tanque.status.get_pos();
+            let Some(evento) = tanque.juega(id, self.pasos_por_turno) else {
+                continue;
+            };
+            eventos.push(evento);
+            match evento {
+                Evento::Accion {
+                    accion: Accion::Dispara(arma),
+                    ..
+                } => {
+                    let impacto = self.dispara(id, arma);
+                    eventos.extend(impacto);
+                    match impacto {
+                        Some(Evento::Impacto { objetivo, .. }) => {
+                            let tanque = &mut self.tanques[objetivo];
+                            if tanque.status.health() == 0 {
+                                tanque.estado = EstadoTanque::Destruido;
+                                eventos.push(Evento::Destruido { tanque: objetivo });
+                            }
+                        }
+                        // The crate no longer blocks anyone's radar
+                        Some(Evento::CajaDestruida { .. }) => {
+                            for otro in 0..self.tanques.len() {
+                                self.sensa(otro);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Evento::Accion {
+                    accion: Accion::Avanza,
+                    ..
+                } => {
+                    self.bloquea(id, antes);
+                    if self.tanques[id].status.crashed() {
+                        eventos.push(Evento::Choque { tanque: id });
+                    }
+                }
+                _ => {}
+            }
+            // The interpreter doesn't know the board, so its own reading
+            // after gira or avanza can see through obstacles
+            self.sensa(id);
+        }
+        eventos
+    }
+
+    /// Pasa a `tanque` la lectura del `radar` según el tablero y sus demás
+    /// sensores.
+    fn sensa(&mut self, tanque: usize) {
+        let tanque = &mut self.tanques[tanque];
+        let status = &tanque.status;
+        let radar = self.tablero.radar(status.get_pos(), status.get_dir());
+        tanque
+            .interprete
+            .sensa(status, radar.try_into().unwrap_or(isize::MAX));
+    }
+
+    /// Regresa a `tanque` a `antes` si avanzó a un obstáculo o a la casilla de
+    /// otro tanque.
+    fn bloquea(&mut self, tanque: usize, antes: (usize, usize)) {
+        let pos = self.tanques[tanque].status.get_pos();
+        let ocupada = self.tablero.get(pos).is_some_and(Casilla::bloquea)
+            || self
+                .tanques
+                .iter()
+                .enumerate()
+                .any(|(otro, t)| otro != tanque && t.status.get_pos() == pos);
+        if ocupada {
+            let status = &mut self.tanques[tanque].status;
+            status.set_pos(antes.0, antes.1);
+            status.set_crashed(true);
+        }
+    }
+
+    /// Sigue el disparo de `tanque` en la dirección en que apunta, hasta el
+    /// alcance de `arma`. Daña al primer tanque que encuentra o destruye la
+    /// primera caja; los demás obstáculos lo detienen, y pasa sobre los
+    /// tanques destruidos.
+    fn dispara(&mut self, tanque: usize, arma: Arma) -> Option<Evento> {
+        let (dano, alcance) = potencia(arma);
+        let status = &self.tanques[tanque].status;
+        let dir = status.get_dir();
+        let mut pos = status.get_pos();
+        for _ in 0..alcance {
+            pos = next_pos(pos, dir)?;
+            let casilla = self.tablero.get(pos)?;
+            if casilla == Casilla::Caja {
+                self.tablero.set(pos, Casilla::Libre);
+                return Some(Evento::CajaDestruida { tanque, pos });
+            }
+            if casilla.bloquea() {
+                return None;
+            }
+            if let Some(objetivo) = self.tanques.iter().position(|otro| {
+                otro.estado != EstadoTanque::Destruido && otro.status.get_pos() == pos
+            }) {
+                let status = &mut self.tanques[objetivo].status;
+                status.apply_damage(dano);
+                status.set_got_shot(true);
+                return Some(Evento::Impacto { tanque, objetivo });
+            }
+        }
+        None
+    }
+}
@@ -1,9 +1,859 @@
+use crate::ast::*;
+use crate::error::{argumentos, ErrorCompilacion};
+use crate::interprete::SOLO_LECTURA;
+use pest::error::{Error, ErrorVariant, InputLocation};
+use pest::iterators::{Pair, Pairs};
+use pest::prec_climber::*;
+use pest::Parser;
 pub use pest_derive::Parser;
+use std::sync::Arc;
 
 #[derive(Parser)]
 #[grammar = "gramatica.pest"]
 pub struct ParserTanques;
 
+/// Analiza `prog` y construye su árbol de sintaxis. El error va en una caja
+/// porque carga la línea del código fuente para mostrarla.
+pub fn compile(prog: &str) -> Result<Program, Box<ErrorCompilacion>> {
+    build_program(prog).map_err(|e| Box::new(error_compilacion(*e, prog)))
+}
+
+/// Niveles de bloques, paréntesis y operadores unarios seguidos que puede
+/// anidar un programa. El análisis y el árbol de sintaxis son recursivos, así
+/// que sin un límite un programa muy anidado desbordaría la pila.
+pub(crate) const MAX_ANIDAMIENTO: usize = 32;
+
+/// Lugar del programa donde está la instrucción que se construye, para rechazar
+/// las que no están permitidas ahí.
+#[derive(Debug, Clone, Copy, Default)]
+struct Contexto {
+    en_funcion: bool,
+    en_ciclo: bool,
+}
+
+impl Contexto {
+    /// Contexto del cuerpo de un ciclo.
+    fn en_ciclo(self) -> Self {
+        Contexto {
+            en_ciclo: true,
+            ..self
+        }
+    }
+}
+
+fn build_program(prog: &str) -> Result<Program, Box<Error<Rule>>> {
+    verifica_anidamiento(prog)?;
+    let pairs = ParserTanques::parse(Rule::prog, prog).map_err(Box::new)?;
+    let mut body = vec![];
+    let mut funciones: Vec<Funcion> = vec![];
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::EOI => {}
+            Rule::funcion => {
+                let funcion = build_funcion(pair)?;
+                if funcion.nombre == "abs" {
+                    return Err(error_en(
+                        prog,
+                        funcion.span,
+                        "`abs` es una función predefinida".to_string(),
+                    ));
+                }
+                if funciones.iter().any(|f| f.nombre == funcion.nombre) {
+                    return Err(error_en(
+                        prog,
+                        funcion.span,
+                        format!("la función `{}` ya está definida", funcion.nombre),
+                    ));
+                }
+                funciones.push(funcion);
+            }
+            _ => body.push(build_stmt(pair, Contexto::default())?),
+        }
+    }
+    let program = Program {
+        body: body.into(),
+        funciones,
+    };
+    verifica_llamadas(&program, prog)?;
+    Ok(program)
+}
+
+/// Revisa, antes de analizar `prog`, que no pase de `MAX_ANIDAMIENTO` niveles.
+/// Cuenta las llaves y paréntesis abiertos más los `-`, `!` y `no` seguidos.
+fn verifica_anidamiento(prog: &str) -> Result<(), Box<Error<Rule>>> {
+    let mut abiertos = 0usize;
+    let mut unarios = 0usize;
+    let mut chars = prog.char_indices().peekable();
+    while let Some((inicio, c)) = chars.next() {
+        let mut fin = inicio + c.len_utf8();
+        match c {
+            '/' if chars.next_if(|&(_, c)| c == '/').is_some() => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '(' | '{' => {
+                abiertos += 1;
+                unarios = 0;
+            }
+            ')' | '}' => {
+                abiertos = abiertos.saturating_sub(1);
+                unarios = 0;
+            }
+            // `!=` is a comparison; its `=` is consumed here
+            '!' if chars.next_if(|&(_, c)| c == '=').is_none() => unarios += 1,
+            '-' => unarios += 1,
+            c if c.is_alphanumeric() || c == '_' => {
+                while let Some((i, c)) = chars.next_if(|&(_, c)| c.is_alphanumeric() || c == '_') {
+                    fin = i + c.len_utf8();
+                }
+                if &prog[inicio..fin] == "no" {
+                    unarios += 1;
+                } else {
+                    unarios = 0;
+                }
+            }
+            _ => unarios = 0,
+        }
+        if abiertos + unarios > MAX_ANIDAMIENTO {
+            return Err(Box::new(Error::new_from_span(
+                ErrorVariant::CustomError {
+                    message: format!(
+                        "hay más de {} niveles anidados de bloques, paréntesis u operadores",
+                        MAX_ANIDAMIENTO
+                    ),
+                },
+                pest::Span::new(prog, inicio, fin).unwrap(),
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn build_funcion(pair: Pair<Rule>) -> Result<Funcion, Box<Error<Rule>>> {
+    let span = pair.as_span().into();
+    let mut pairs = inner(pair);
+    let nombre = build_nombre(pairs.next().unwrap())?;
+    let mut params: Vec<String> = vec![];
+    for param in inner(pairs.next().unwrap()) {
+        build_nombre(param.clone())?;
+        if params.iter().any(|p| p == param.as_str()) {
+            return Err(Box::new(Error::new_from_span(
+                ErrorVariant::CustomError {
+                    message: format!("el parámetro `{}` está repetido", param.as_str()),
+                },
+                param.as_span(),
+            )));
+        }
+        params.push(param.as_str().to_string());
+    }
+    let cuerpo = build_block(
+        pairs.next().unwrap().into_inner(),
+        Contexto {
+            en_funcion: true,
+            en_ciclo: false,
+        },
+    )?;
+    Ok(Funcion {
+        nombre,
+        params,
+        cuerpo,
+        span,
+    })
+}
+
+/// Nombre de una variable, parámetro o función que se está declarando, o de
+/// una variable que se asigna.
+fn build_nombre(pair: Pair<Rule>) -> Result<String, Box<Error<Rule>>> {
+    let message = if pair.as_rule() == Rule::palabra_reservada {
+        format!(
+            "`{}` es una palabra reservada y no puede usarse como nombre",
+            pair.as_str()
+        )
+    } else if SOLO_LECTURA.contains(&pair.as_str()) {
+        format!("`{}` es una variable de solo lectura", pair.as_str())
+    } else {
+        return Ok(pair.as_str().to_string());
+    };
+    Err(Box::new(Error::new_from_span(
+        ErrorVariant::CustomError { message },
+        pair.as_span(),
+    )))
+}
+
+fn build_block(pairs: Pairs<Rule>, ctx: Contexto) -> Result<Block, Box<Error<Rule>>> {
+    pairs.map(|pair| build_stmt(pair, ctx)).collect()
+}
+
+fn build_stmt(pair: Pair<Rule>, ctx: Contexto) -> Result<Stmt, Box<Error<Rule>>> {
+    let pest_span = pair.as_span();
+    let span = Span::from(pest_span.clone());
+    let rule = pair.as_rule();
+    let mut pairs = inner(pair);
+    let kind = match rule {
+        Rule::decl | Rule::asig | Rule::decl_para | Rule::asig_para => {
+            let name = build_nombre(pairs.next().unwrap())?;
+            let value = build_expr(pairs.next().unwrap())?;
+            if matches!(rule, Rule::decl | Rule::decl_para) {
+                StmtKind::Decl { name, value }
+            } else {
+                StmtKind::Asig { name, value }
+            }
+        }
+        Rule::bloque_si => build_si(pairs, span, ctx)?,
+        Rule::bloque_mientras => {
+            let cond = build_expr(pairs.next().unwrap())?;
+            let bloque = build_block(pairs.next().unwrap().into_inner(), ctx.en_ciclo())?;
+            StmtKind::Mientras { cond, bloque }
+        }
+        Rule::bloque_para => {
+            let init = build_stmt(pairs.next().unwrap(), ctx)?;
+            let cond = build_expr(pairs.next().unwrap())?;
+            let update = build_stmt(pairs.next().unwrap(), ctx)?;
+            let bloque = build_block(pairs.next().unwrap().into_inner(), ctx.en_ciclo())?;
+            StmtKind::Para {
+                init: Arc::new(init),
+                cond,
+                update: Arc::new(update),
+                bloque,
+            }
+        }
+        Rule::bloque_repite => {
+            let veces = build_expr(pairs.next().unwrap())?;
+            let bloque = build_block(pairs.next().unwrap().into_inner(), ctx.en_ciclo())?;
+            StmtKind::Repite { veces, bloque }
+        }
+        Rule::llamada_inst => StmtKind::Llamada(build_llamada(pairs.next().unwrap())?),
+        Rule::regresa => {
+            if !ctx.en_funcion {
+                return Err(fuera_de(pest_span, "regresa", "una función"));
+            }
+            StmtKind::Regresa(pairs.next().map(build_expr).transpose()?)
+        }
+        Rule::rompe | Rule::continua => {
+            let (kind, inst) = if rule == Rule::rompe {
+                (StmtKind::Rompe, "rompe")
+            } else {
+                (StmtKind::Continua, "continua")
+            };
+            if !ctx.en_ciclo {
+                return Err(fuera_de(pest_span, inst, "un ciclo"));
+            }
+            kind
+        }
+        Rule::gira => {
+            let dir = match pairs.next().unwrap().as_str() {
+                "izquierda" => DirGiro::Izquierda,
+                _ => DirGiro::Derecha,
+            };
+            StmtKind::Gira(dir)
+        }
+        Rule::avanza => StmtKind::Avanza,
+        Rule::dispara => {
+            let arma = match pairs.next().map(|arma| arma.as_str()) {
+                Some("grande") => Arma::Grande,
+                _ => Arma::Chico,
+            };
+            StmtKind::Dispara(arma)
+        }
+        r => return Err(unexpected_rule(r, pest_span)),
+    };
+    Ok(Stmt { kind, span })
+}
+
+/// Construye un `si` con sus ramas `sino si` y `otro`. Cada `sino si` se
+/// convierte en un `si` dentro del `otro` de la rama anterior.
+fn build_si<'a>(
+    mut pairs: impl Iterator<Item = Pair<'a, Rule>>,
+    span: Span,
+    ctx: Contexto,
+) -> Result<StmtKind, Box<Error<Rule>>> {
+    let cond = build_expr(pairs.next().unwrap())?;
+    let bloque = build_block(pairs.next().unwrap().into_inner(), ctx)?;
+    // The chain is built from the end, the last branch is the innermost
+    let mut otro = None;
+    for pair in pairs.collect::<Vec<_>>().into_iter().rev() {
+        let rule = pair.as_rule();
+        if rule == Rule::rama_sobrante {
+            return Err(Box::new(Error::new_from_span(
+                ErrorVariant::CustomError {
+                    message: if pair.as_str().starts_with("otro") {
+                        "un `si` solo puede tener un `otro`".to_string()
+                    } else {
+                        "el `sino si` debe ir antes del `otro`".to_string()
+                    },
+                },
+                pair.as_span(),
+            )));
+        }
+        let span_rama = Span::from(pair.as_span()).join(span);
+        let mut pairs = inner(pair);
+        otro = Some(match rule {
+            Rule::sino_si => {
+                let cond = build_expr(pairs.next().unwrap())?;
+                let bloque = build_block(pairs.next().unwrap().into_inner(), ctx)?;
+                Block::from([Stmt {
+                    kind: StmtKind::Si { cond, bloque, otro },
+                    span: span_rama,
+                }])
+            }
+            _ => build_block(pairs.next().unwrap().into_inner(), ctx)?,
+        });
+    }
+    Ok(StmtKind::Si { cond, bloque, otro })
+}
+
+/// Construye una expresión a partir de un par `expr`, `expr_par`,
+/// `expr_arit`, `comparacion` o de cualquiera de sus términos.
+///
+/// La precedencia, de menor a mayor, es: `||`, `&&`, negación (`!`, `no`),
+/// comparaciones, `+ -` y `* / %`. Todos los operadores binarios asocian a la
+/// izquierda, así que `a || b && c` equivale a `a || (b && c)`. La negación y
+/// las comparaciones no pasan por `PrecClimber`: su lugar en la precedencia lo
+/// fija la gramática.
+pub fn build_expr(pair: Pair<Rule>) -> Result<Expr, Box<Error<Rule>>> {
+    let climber = PrecClimber::new(vec![
+        Operator::new(Rule::or, Assoc::Left),
+        Operator::new(Rule::and, Assoc::Left),
+        Operator::new(Rule::suma, Assoc::Left) | Operator::new(Rule::resta, Assoc::Left),
+        Operator::new(Rule::mult, Assoc::Left)
+            | Operator::new(Rule::div, Assoc::Left)
+            | Operator::new(Rule::modulo, Assoc::Left),
+    ]);
+
+    let infix = |lhs: Result<Expr, Box<Error<Rule>>>,
+                 op: Pair<Rule>,
+                 rhs: Result<Expr, Box<Error<Rule>>>| {
+        let (lhs, rhs) = (lhs?, rhs?);
+        let op = match op.as_rule() {
+            Rule::suma => BinOp::Suma,
+            Rule::resta => BinOp::Resta,
+            Rule::mult => BinOp::Mult,
+            Rule::div => BinOp::Div,
+            Rule::modulo => BinOp::Modulo,
+            Rule::or | Rule::and => {
+                let op = match op.as_rule() {
+                    Rule::or => LogicOp::Or,
+                    _ => LogicOp::And,
+                };
+                return Ok(Expr {
+                    span: lhs.span.join(rhs.span),
+                    kind: ExprKind::LogicOp {
+                        op,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    },
+                });
+            }
+            r => return Err(unexpected_rule(r, op.as_span())),
+        };
+        Ok(Expr {
+            span: lhs.span.join(rhs.span),
+            kind: ExprKind::BinOp {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            },
+        })
+    };
+
+    let primary = |pair: Pair<Rule>| match pair.as_rule() {
+        Rule::expr | Rule::expr_par | Rule::expr_arit => build_expr(pair),
+        Rule::comparacion => {
+            let span = pair.as_span().into();
+            let mut pairs = pair.into_inner();
+            let lhs = build_expr(pairs.next().unwrap())?;
+            let Some(op) = pairs.next() else {
+                return Ok(lhs);
+            };
+            let op = match op.as_rule() {
+                Rule::men => CompOp::Men,
+                Rule::men_ig => CompOp::MenIg,
+                Rule::may => CompOp::May,
+                Rule::may_ig => CompOp::MayIg,
+                Rule::ig => CompOp::Ig,
+                Rule::no_ig => CompOp::NoIg,
+                r => return Err(unexpected_rule(r, op.as_span())),
+            };
+            let rhs = build_expr(pairs.next().unwrap())?;
+            Ok(Expr {
+                kind: ExprKind::Comp {
+                    op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                },
+                span,
+            })
+        }
+        Rule::negacion => {
+            let span = pair.as_span().into();
+            let expr = build_expr(pair.into_inner().nth(1).unwrap())?;
+            Ok(Expr {
+                kind: ExprKind::UnOp {
+                    op: UnOp::No,
+                    expr: Box::new(expr),
+                },
+                span,
+            })
+        }
+        Rule::verdadero | Rule::falso => Ok(Expr {
+            kind: ExprKind::Bool(pair.as_rule() == Rule::verdadero),
+            span: pair.as_span().into(),
+        }),
+        Rule::int => Ok(Expr {
+            kind: ExprKind::Int(parse_int(pair.as_str(), pair.as_span())?),
+            span: pair.as_span().into(),
+        }),
+        Rule::nom_var => Ok(Expr {
+            kind: ExprKind::Var(pair.as_str().to_string()),
+            span: pair.as_span().into(),
+        }),
+        Rule::llamada => Ok(Expr {
+            span: pair.as_span().into(),
+            kind: ExprKind::Llamada(build_llamada(pair)?),
+        }),
+        Rule::negativo | Rule::abs => {
+            let span_pest = pair.as_span();
+            let span = span_pest.clone().into();
+            let op = match pair.as_rule() {
+                Rule::negativo => UnOp::Negativo,
+                _ => UnOp::Abs,
+            };
+            let expr = inner(pair).find(|p| p.as_rule() != Rule::resta).unwrap();
+            if op == UnOp::Negativo && expr.as_rule() == Rule::negativo {
+                return Err(dos_signos(span_pest));
+            }
+            // A negative literal is a single number, which also lets the
+            // smallest one be written
+            if op == UnOp::Negativo && expr.as_rule() == Rule::int {
+                return Ok(Expr {
+                    kind: ExprKind::Int(parse_int(&format!("-{}", expr.as_str()), span_pest)?),
+                    span,
+                });
+            }
+            Ok(Expr {
+                span,
+                kind: ExprKind::UnOp {
+                    op,
+                    expr: Box::new(build_expr(expr)?),
+                },
+            })
+        }
+        r => Err(unexpected_rule(r, pair.as_span())),
+    };
+
+    if pair.as_rule() == Rule::expr_arit {
+        // `x - -1` would stack a subtraction and a unary minus
+        let pairs: Vec<_> = inner(pair.clone()).collect();
+        for par in pairs.windows(2) {
+            if par[0].as_rule() == Rule::resta && par[1].as_rule() == Rule::negativo {
+                let (inicio, fin) = (par[0].as_span(), par[1].as_span());
+                return Err(dos_signos(inicio.start_pos().span(&fin.end_pos())));
+            }
+        }
+    }
+
+    match pair.as_rule() {
+        Rule::expr | Rule::expr_par | Rule::expr_arit => climber.climb(inner(pair), primary, infix),
+        _ => primary(pair),
+    }
+}
+
+/// Número entero de `texto`, que viene de la región `span` del programa.
+fn parse_int(texto: &str, span: pest::Span) -> Result<isize, Box<Error<Rule>>> {
+    texto.parse().map_err(|_| {
+        Box::new(Error::new_from_span(
+            ErrorVariant::CustomError {
+                message: format!("número inválido `{}`", texto),
+            },
+            span,
+        ))
+    })
+}
+
+/// Error para dos `-` seguidos en `span`, como en `--x` o `x - -1`.
+fn dos_signos(span: pest::Span) -> Box<Error<Rule>> {
+    Box::new(Error::new_from_span(
+        ErrorVariant::CustomError {
+            message: "no puede haber dos `-` seguidos; usa `-(-x)`".to_string(),
+        },
+        span,
+    ))
+}
+
+fn build_llamada(pair: Pair<Rule>) -> Result<Llamada, Box<Error<Rule>>> {
+    let mut pairs = inner(pair);
+    let nombre = pairs.next().unwrap().as_str().to_string();
+    let args = pairs.map(build_expr).collect::<Result<_, _>>()?;
+    Ok(Llamada { nombre, args })
+}
+
+/// Revisa que todas las llamadas del programa sean a funciones definidas y con
+/// el número correcto de argumentos.
+fn verifica_llamadas(program: &Program, prog: &str) -> Result<(), Box<Error<Rule>>> {
+    let mut llamadas = vec![];
+    llamadas_bloque(&program.body, &mut llamadas);
+    for funcion in &program.funciones {
+        llamadas_bloque(&funcion.cuerpo, &mut llamadas);
+    }
+    for (llamada, span) in llamadas {
+        // La gramática reconoce `abs` con un argumento dentro de una
+        // expresión; aquí solo llegan los otros usos
+        let params = if llamada.nombre == "abs" {
+            if llamada.args.len() == 1 {
+                return Err(error_en(
+                    prog,
+                    span,
+                    "`abs` regresa un valor y solo puede usarse en una expresión".to_string(),
+                ));
+            }
+            1
+        } else {
+            program
+                .funciones
+                .iter()
+                .find(|f| f.nombre == llamada.nombre)
+                .ok_or_else(|| {
+                    error_en(
+                        prog,
+                        span,
+                        format!("la función `{}` no está definida", llamada.nombre),
+                    )
+                })?
+                .params
+                .len()
+        };
+        if params != llamada.args.len() {
+            return Err(error_en(
+                prog,
+                span,
+                format!(
+                    "la función `{}` recibe {}, pero se le pasaron {}",
+                    llamada.nombre,
+                    argumentos(params),
+                    llamada.args.len()
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn llamadas_bloque<'a>(block: &'a [Stmt], llamadas: &mut Vec<(&'a Llamada, Span)>) {
+    for stmt in block {
+        match &stmt.kind {
+            StmtKind::Decl { value, .. } | StmtKind::Asig { value, .. } => {
+                llamadas_expr(value, llamadas)
+            }
+            StmtKind::Si { cond, bloque, otro } => {
+                llamadas_expr(cond, llamadas);
+                llamadas_bloque(bloque, llamadas);
+                if let Some(otro) = otro {
+                    llamadas_bloque(otro, llamadas);
+                }
+            }
+            StmtKind::Mientras { cond, bloque } => {
+                llamadas_expr(cond, llamadas);
+                llamadas_bloque(bloque, llamadas);
+            }
+            StmtKind::Para {
+                init,
+                cond,
+                update,
+                bloque,
+            } => {
+                llamadas_bloque(std::slice::from_ref(&**init), llamadas);
+                llamadas_expr(cond, llamadas);
+                llamadas_bloque(std::slice::from_ref(&**update), llamadas);
+                llamadas_bloque(bloque, llamadas);
+            }
+            StmtKind::Repite { veces, bloque } => {
+                llamadas_expr(veces, llamadas);
+                llamadas_bloque(bloque, llamadas);
+            }
+            StmtKind::Llamada(llamada) => {
+                llamadas.push((llamada, stmt.span));
+                for arg in &llamada.args {
+                    llamadas_expr(arg, llamadas);
+                }
+            }
+            StmtKind::Regresa(Some(value)) => llamadas_expr(value, llamadas),
+            StmtKind::Regresa(None)
+            | StmtKind::Rompe
+            | StmtKind::Continua
+            | StmtKind::Gira(_)
+            | StmtKind::Avanza
+            | StmtKind::Dispara(_) => {}
+        }
+    }
+}
+
+fn llamadas_expr<'a>(expr: &'a Expr, llamadas: &mut Vec<(&'a Llamada, Span)>) {
+    match &expr.kind {
+        ExprKind::Int(_) | ExprKind::Bool(_) | ExprKind::Var(_) => {}
+        ExprKind::Llamada(llamada) => {
+            llamadas.push((llamada, expr.span));
+            for arg in &llamada.args {
+                llamadas_expr(arg, llamadas);
+            }
+        }
+        ExprKind::BinOp { lhs, rhs, .. }
+        | ExprKind::Comp { lhs, rhs, .. }
+        | ExprKind::LogicOp { lhs, rhs, .. } => {
+            llamadas_expr(lhs, llamadas);
+            llamadas_expr(rhs, llamadas);
+        }
+        ExprKind::UnOp { expr, .. } => llamadas_expr(expr, llamadas),
+    }
+}
+
+/// Hijos de `pair` sin los signos de puntuación, que solo existen como reglas
+/// para mejorar los errores de sintaxis.
+fn inner(pair: Pair<Rule>) -> impl Iterator<Item = Pair<Rule>> {
+    pair.into_inner().filter(|pair| {
+        !matches!(
+            pair.as_rule(),
+            Rule::pyc
+                | Rule::igual
+                | Rule::par_abre
+                | Rule::par_cierra
+                | Rule::llave_abre
+                | Rule::llave_cierra
+                | Rule::coma
+        )
+    })
+}
+
+/// Error por usar la instrucción `inst` fuera de `lugar`.
+fn fuera_de(span: pest::Span, inst: &str, lugar: &str) -> Box<Error<Rule>> {
+    Box::new(Error::new_from_span(
+        ErrorVariant::CustomError {
+            message: format!("`{}` solo puede usarse dentro de {}", inst, lugar),
+        },
+        span,
+    ))
+}
+
+/// Error con un mensaje propio en la región `span` de `prog`.
+fn error_en(prog: &str, span: Span, message: String) -> Box<Error<Rule>> {
+    Box::new(Error::new_from_span(
+        ErrorVariant::CustomError { message },
+        pest::Span::new(prog, span.start, span.end).unwrap(),
+    ))
+}
+
+fn unexpected_rule(rule: Rule, span: pest::Span) -> Box<Error<Rule>> {
+    Box::new(Error::new_from_span(
+        ErrorVariant::CustomError {
+            message: format!("construcción inesperada: {:?}", rule),
+        },
+        span,
+    ))
+}
+
+/// Traduce un error de `pest` a un error con mensajes para el estudiante.
+fn error_compilacion(e: Error<Rule>, prog: &str) -> ErrorCompilacion {
+    let (start, end) = match e.location {
+        InputLocation::Pos(pos) => (pos, pos + token_en(prog, pos).len()),
+        InputLocation::Span(span) => span,
+    };
+    let encontrado = Some(token_en(prog, start))
+        .filter(|token| !token.is_empty())
+        .map(String::from);
+    let (esperado, mensaje) = match e.variant {
+        ErrorVariant::ParsingError { positives, .. } => {
+            let esperado = describe_rules(&positives);
+            let mut mensaje = format!("se esperaba {}", enumera(&esperado));
+            if let Some(anterior) = token_antes_de(prog, start) {
+                mensaje.push_str(&format!(" después de `{}`", anterior));
+            }
+            (esperado, mensaje)
+        }
+        ErrorVariant::CustomError { message } => (vec![], message),
+    };
+    let span = Span {
+        start,
+        end,
+        ..Span::from(pest::Span::new(prog, start, end).unwrap())
+    };
+    let linea = prog.lines().nth(span.line - 1).unwrap_or("").to_string();
+    ErrorCompilacion {
+        span,
+        esperado,
+        encontrado,
+        mensaje,
+        linea,
+    }
+}
+
+/// Describe en español un conjunto de reglas esperadas. Los grupos comunes,
+/// como todas las instrucciones o todos los operadores, se describen juntos.
+fn describe_rules(rules: &[Rule]) -> Vec<String> {
+    const INSTRUCCIONES: &[Rule] = &[
+        Rule::decl,
+        Rule::regresa,
+        Rule::rompe,
+        Rule::continua,
+        Rule::asig,
+        Rule::bloque_si,
+        Rule::bloque_mientras,
+        Rule::bloque_para,
+        Rule::bloque_repite,
+        Rule::gira,
+        Rule::avanza,
+        Rule::dispara,
+    ];
+    const OPERADORES: &[Rule] = &[Rule::suma, Rule::resta, Rule::mult, Rule::div, Rule::modulo];
+    const COMPARADORES: &[Rule] = &[
+        Rule::men,
+        Rule::men_ig,
+        Rule::may,
+        Rule::may_ig,
+        Rule::ig,
+        Rule::no_ig,
+    ];
+
+    // Después de un término completo puede seguir cualquier operador
+    const TODOS_OPERADORES: &[Rule] = &[
+        Rule::suma,
+        Rule::resta,
+        Rule::mult,
+        Rule::div,
+        Rule::modulo,
+        Rule::men,
+        Rule::men_ig,
+        Rule::may,
+        Rule::may_ig,
+        Rule::ig,
+        Rule::no_ig,
+        Rule::or,
+        Rule::and,
+    ];
+
+    let mut rules = rules.to_vec();
+    let mut descripciones = vec![];
+    for (grupo, descripcion) in [
+        (INSTRUCCIONES, "una instrucción"),
+        (TODOS_OPERADORES, "un operador"),
+        (OPERADORES, "un operador aritmético"),
+        (COMPARADORES, "un comparador"),
+    ] {
+        // Una asignación empieza con un nombre de variable, así que en el
+        // inicio de una instrucción `pest` reporta `nom_var` en lugar de `asig`
+        let completo = grupo.iter().all(|rule| {
+            rules.contains(rule) || (*rule == Rule::asig && rules.contains(&Rule::nom_var))
+        });
+        if completo {
+            rules.retain(|rule| {
+                !(grupo.contains(rule) || grupo == INSTRUCCIONES && *rule == Rule::nom_var)
+            });
+            descripciones.push(descripcion.to_string());
+        }
+    }
+    descripciones.extend(rules.iter().map(|rule| {
+        match rule {
+            Rule::EOI => "el final del programa",
+            Rule::pyc => "`;`",
+            Rule::igual => "`=`",
+            Rule::par_abre => "`(`",
+            Rule::par_cierra => "`)`",
+            Rule::llave_abre => "`{`",
+            Rule::llave_cierra => "`}`",
+            Rule::coma => "`,`",
+            Rule::suma => "`+`",
+            Rule::resta => "`-`",
+            Rule::mult => "`*`",
+            Rule::div => "`/`",
+            Rule::modulo => "`%`",
+            Rule::men => "`<`",
+            Rule::men_ig => "`<=`",
+            Rule::may => "`>`",
+            Rule::may_ig => "`>=`",
+            Rule::ig => "`==`",
+            Rule::no_ig => "`!=`",
+            Rule::or => "`||`",
+            Rule::and => "`&&`",
+            Rule::int => "un número",
+            Rule::nom_var | Rule::palabra_reservada => "un nombre de variable",
+            Rule::expr
+            | Rule::expr_arit
+            | Rule::expr_par
+            | Rule::comparacion
+            | Rule::negativo
+            | Rule::negacion => "una expresión",
+            Rule::abs => "`abs`",
+            Rule::no => "`!`",
+            Rule::verdadero => "`verdadero`",
+            Rule::falso => "`falso`",
+            Rule::dir_giro => "una dirección (`izquierda` o `derecha`)",
+            Rule::arma => "un arma (`chico` o `grande`)",
+            Rule::decl => "`var`",
+            Rule::asig => "una asignación",
+            Rule::bloque_si => "`si`",
+            Rule::sino_si => "`sino si`",
+            Rule::otro => "`otro`",
+            Rule::bloque_mientras => "`mientras`",
+            Rule::bloque_para => "`para`",
+            Rule::bloque_repite => "`repite`",
+            Rule::decl_para => "`var`",
+            Rule::asig_para => "una asignación",
+            Rule::gira => "`gira`",
+            Rule::avanza => "`avanza`",
+            Rule::dispara => "`dispara`",
+            Rule::regresa => "`regresa`",
+            Rule::rompe => "`rompe`",
+            Rule::continua => "`continua`",
+            Rule::funcion => "`funcion`",
+            Rule::llamada | Rule::llamada_inst => "una llamada a función",
+            _ => "una instrucción",
+        }
+        .to_string()
+    }));
+    descripciones.dedup();
+    descripciones
+}
+
+/// Une descripciones como "`a`, `b` o `c`".
+fn enumera(descripciones: &[String]) -> String {
+    match descripciones {
+        [] => "otra cosa".to_string(),
+        [unica] => unica.clone(),
+        [resto @ .., ultima] => format!("{} o {}", resto.join(", "), ultima),
+    }
+}
+
+/// Palabra o símbolo que empieza en la posición `pos` de `prog`.
+fn token_en(prog: &str, pos: usize) -> &str {
+    let resto = &prog[pos..];
+    let mut chars = resto.char_indices();
+    match chars.next() {
+        Some((_, c)) if c.is_alphanumeric() || c == '_' => {
+            let fin = chars
+                .find(|(_, c)| !(c.is_alphanumeric() || *c == '_'))
+                .map_or(resto.len(), |(i, _)| i);
+            &resto[..fin]
+        }
+        Some((_, c)) => &resto[..c.len_utf8()],
+        None => "",
+    }
+}
+
+/// Última palabra o símbolo antes de la posición `pos` de `prog`.
+fn token_antes_de(prog: &str, pos: usize) -> Option<&str> {
+    let anterior = prog[..pos].trim_end();
+    let c = anterior.chars().next_back()?;
+    let inicio = if c.is_alphanumeric() || c == '_' {
+        anterior
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !(c.is_alphanumeric() || *c == '_'))
+            .map_or(0, |(i, c)| i + c.len_utf8())
+    } else {
+        anterior.len() - c.len_utf8()
+    };
+    Some(&anterior[inicio..])
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -19,6 +869,35 @@ mod test {
         assert!(decl_parse_result.is_ok());
     }
 
+    #[test]
+    pub fn test_nombres() {
+        for nombre in [
+            "x1",
+            "pasos_restantes",
+            "_tmp",
+            "dirección",
+            "año",
+            "Ángulo",
+            "variable",
+            "sino_si",
+            "nota",
+            "avanzar",
+        ] {
+            let prog = compile(&format!("var {0} = 1; {0} = {0} + 1;", nombre)).unwrap();
+            match &prog.body[1].kind {
+                StmtKind::Asig { name, .. } => assert_eq!(name, nombre),
+                k => panic!("{:?}", k),
+            }
+        }
+        assert!(compile("var 1x = 1;").is_err());
+        assert!(compile("funcion gira_dos() { gira derecha; gira derecha; } gira_dos();").is_ok());
+
+        // Un nombre que empieza con una palabra reservada no se confunde con
+        // ella: esto no declara la variable `iable`
+        let prog = compile("variable = 1;").unwrap();
+        assert!(matches!(prog.body[0].kind, StmtKind::Asig { .. }));
+    }
+
     #[test]
     pub fn test_prog() {
         let prog = ParserTanques::parse(Rule::prog, "var x = 1;\nvar wey = 4;");
@@ -27,4 +906,265 @@ mod test {
         let prog = ParserTanques::parse(Rule::prog, "\nvar x = 1;\n\n");
         assert!(prog.is_ok());
     }
+
+    #[test]
+    pub fn test_compile() {
+        let prog =
+            compile("var x = 1 + 2 * y;\nsi(x < 3){ avanza; } otro { gira izquierda; }").unwrap();
+        assert_eq!(prog.body.len(), 2);
+        match &prog.body[0].kind {
+            StmtKind::Decl { name, value } => {
+                assert_eq!(name, "x");
+                match &value.kind {
+                    ExprKind::BinOp { op, rhs, .. } => {
+                        assert_eq!(*op, BinOp::Suma);
+                        assert!(matches!(
+                            rhs.kind,
+                            ExprKind::BinOp {
+                                op: BinOp::Mult,
+                                ..
+                            }
+                        ));
+                    }
+                    k => panic!("{:?}", k),
+                }
+                assert_eq!((value.span.col, value.span.end_col), (9, 18));
+            }
+            k => panic!("{:?}", k),
+        }
+        match &prog.body[1].kind {
+            StmtKind::Si { bloque, otro, .. } => {
+                assert_eq!(bloque[0].kind, StmtKind::Avanza);
+                assert_eq!(
+                    otro.as_ref().unwrap()[0].kind,
+                    StmtKind::Gira(DirGiro::Izquierda)
+                );
+            }
+            k => panic!("{:?}", k),
+        }
+        assert_eq!(prog.body[1].span.line, 2);
+
+        let prog = compile("dispara; dispara chico; dispara grande;").unwrap();
+        let armas: Vec<_> = prog.body.iter().map(|stmt| &stmt.kind).collect();
+        assert_eq!(
+            armas,
+            [
+                &StmtKind::Dispara(Arma::Chico),
+                &StmtKind::Dispara(Arma::Chico),
+                &StmtKind::Dispara(Arma::Grande)
+            ]
+        );
+
+        assert!(compile("var x = --5;").is_err());
+        assert!(compile("var x = - -5;").is_err());
+        assert!(compile("var x = -(-5);").is_ok());
+        // Un número negativo es un `-` unario, que tampoco se junta con la resta
+        assert!(compile("var x = x--1;").is_err());
+        assert!(compile("var x = x - -1;").is_err());
+        assert!(compile("var x = x - (-1);").is_ok());
+        assert!(compile("var x = x + -1;").is_ok());
+        let prog = compile("var x = -9223372036854775808;").unwrap();
+        match &prog.body[0].kind {
+            StmtKind::Decl { value, .. } => assert_eq!(value.kind, ExprKind::Int(isize::MIN)),
+            k => panic!("{:?}", k),
+        }
+        assert!(compile("var x = 1 < 2 < 3;").is_err());
+        assert!(compile("var x = (1 < 2) == verdadero;").is_ok());
+
+        let prog = compile("var x = -abs(y) % 3;").unwrap();
+        match &prog.body[0].kind {
+            StmtKind::Decl { value, .. } => match &value.kind {
+                ExprKind::BinOp {
+                    op: BinOp::Modulo,
+                    lhs,
+                    ..
+                } => match &lhs.kind {
+                    ExprKind::UnOp {
+                        op: UnOp::Negativo,
+                        expr,
+                    } => assert!(matches!(expr.kind, ExprKind::UnOp { op: UnOp::Abs, .. })),
+                    k => panic!("{:?}", k),
+                },
+                k => panic!("{:?}", k),
+            },
+            k => panic!("{:?}", k),
+        }
+
+        let prog = compile("funcion f(a, b) { regresa a * b; }\nvar x = f(1, 2);").unwrap();
+        assert_eq!(prog.funciones.len(), 1);
+        let funcion = &prog.funciones[0];
+        assert_eq!(funcion.params, vec!["a", "b"]);
+        assert!(matches!(funcion.cuerpo[0].kind, StmtKind::Regresa(Some(_))));
+        match &prog.body[0].kind {
+            StmtKind::Decl { value, .. } => match &value.kind {
+                ExprKind::Llamada(llamada) => {
+                    assert_eq!(llamada.nombre, "f");
+                    assert_eq!(llamada.args.len(), 2);
+                }
+                k => panic!("{:?}", k),
+            },
+            k => panic!("{:?}", k),
+        }
+    }
+
+    #[test]
+    pub fn test_errores_compilacion() {
+        let err = compile("var x = 1\navanza;").unwrap_err();
+        assert_eq!(err.mensaje, "se esperaba un operador o `;` después de `1`");
+        assert_eq!((err.span.line, err.span.col), (2, 1));
+        assert_eq!(err.encontrado.as_deref(), Some("avanza"));
+        assert_eq!(err.linea, "avanza;");
+        assert_eq!(
+            err.to_string(),
+            "error: se esperaba un operador o `;` después de `1`\n \
+             --> línea 2, columna 1\n  |\n2 | avanza;\n  | ^^^^^^"
+        );
+
+        let err = compile("si(x == 1 { avanza; }").unwrap_err();
+        assert_eq!(
+            err.esperado,
+            vec!["un operador aritmético", "`)`", "`||`", "`&&`"]
+        );
+
+        let err = compile("si(x == 1){ avanza; ").unwrap_err();
+        assert_eq!(
+            err.mensaje,
+            "se esperaba una instrucción o `}` después de `;`"
+        );
+        assert_eq!(err.encontrado, None);
+
+        let err = compile("var x = --5;").unwrap_err();
+        assert_eq!(err.mensaje, "no puede haber dos `-` seguidos; usa `-(-x)`");
+        assert_eq!((err.span.col, err.span.end_col), (9, 12));
+
+        let err = compile("var x = 1 - -y;").unwrap_err();
+        assert_eq!(err.mensaje, "no puede haber dos `-` seguidos; usa `-(-x)`");
+        assert_eq!((err.span.col, err.span.end_col), (11, 15));
+
+        // El anidamiento tiene un límite para no desbordar la pila
+        let bloques = |n| "si(verdadero){ ".repeat(n) + &"} ".repeat(n);
+        assert!(compile(&bloques(MAX_ANIDAMIENTO)).is_ok());
+        let err = compile(&bloques(MAX_ANIDAMIENTO + 1)).unwrap_err();
+        assert_eq!(
+            err.mensaje,
+            "hay más de 32 niveles anidados de bloques, paréntesis u operadores"
+        );
+        assert_eq!(err.span.col, 15 * MAX_ANIDAMIENTO + 3);
+        let parentesis = |n| format!("var x = {}1{};", "(".repeat(n), ")".repeat(n));
+        assert!(compile(&parentesis(MAX_ANIDAMIENTO)).is_ok());
+        assert!(compile(&parentesis(MAX_ANIDAMIENTO + 1)).is_err());
+        let negaciones = format!("var x = {}verdadero;", "no !".repeat(MAX_ANIDAMIENTO));
+        assert!(compile(&negaciones).is_err());
+        // Los comentarios, `!=` y las restas no cuentan
+        let planos = format!(
+            "// {}\nvar x = 1 != 2 && {}1 == 1;",
+            "(".repeat(100),
+            "1 - ".repeat(100)
+        );
+        assert!(compile(&planos).is_ok());
+
+        let err = compile("var x = 99999999999999999999;").unwrap_err();
+        assert_eq!(err.mensaje, "número inválido `99999999999999999999`");
+
+        let err = compile("avanza;\nregresa 1;").unwrap_err();
+        assert_eq!(
+            err.mensaje,
+            "`regresa` solo puede usarse dentro de una función"
+        );
+        assert_eq!(err.span.line, 2);
+
+        let err = compile("var x = f(1) + 2;").unwrap_err();
+        assert_eq!(err.mensaje, "la función `f` no está definida");
+        assert_eq!((err.span.col, err.span.end_col), (9, 13));
+
+        let err = compile("funcion f(a) { regresa a; }\nf(1, 2);").unwrap_err();
+        assert_eq!(
+            err.mensaje,
+            "la función `f` recibe 1 argumento, pero se le pasaron 2"
+        );
+        assert_eq!(err.span.line, 2);
+
+        let err = compile("funcion f() {}\nfuncion f() {}").unwrap_err();
+        assert_eq!(err.mensaje, "la función `f` ya está definida");
+
+        let err = compile("funcion abs(x) { regresa x; }").unwrap_err();
+        assert_eq!(err.mensaje, "`abs` es una función predefinida");
+        let err = compile("var x = abs();").unwrap_err();
+        assert_eq!(
+            err.mensaje,
+            "la función `abs` recibe 1 argumento, pero se le pasaron 0"
+        );
+        let err = compile("var x = 1 + abs(1, 2);").unwrap_err();
+        assert_eq!(
+            err.mensaje,
+            "la función `abs` recibe 1 argumento, pero se le pasaron 2"
+        );
+        let err = compile("abs(1);").unwrap_err();
+        assert_eq!(
+            err.mensaje,
+            "`abs` regresa un valor y solo puede usarse en una expresión"
+        );
+
+        let err = compile("funcion f(a, a) {}").unwrap_err();
+        assert_eq!(err.mensaje, "el parámetro `a` está repetido");
+
+        let err = compile("si(1 == 1){\n  rompe;\n}").unwrap_err();
+        assert_eq!(err.mensaje, "`rompe` solo puede usarse dentro de un ciclo");
+        assert_eq!((err.span.line, err.span.col), (2, 3));
+
+        // El cuerpo de una función no es parte del ciclo donde se llama
+        let err = compile("funcion f() { continua; }\nmientras(1 == 1){ f(); }").unwrap_err();
+        assert_eq!(
+            err.mensaje,
+            "`continua` solo puede usarse dentro de un ciclo"
+        );
+        assert!(compile("funcion f() { repite 2 { si(1 == 1){ rompe; } } }").is_ok());
+
+        let err =
+            compile("si(1 == 1){ avanza; } otro { avanza; } otro { gira derecha; }").unwrap_err();
+        assert_eq!(err.mensaje, "un `si` solo puede tener un `otro`");
+        assert_eq!((err.span.col, err.span.end_col), (40, 62));
+        let err =
+            compile("si(1 == 1){ avanza; } otro { avanza; } sino si(1 == 2) { }").unwrap_err();
+        assert_eq!(err.mensaje, "el `sino si` debe ir antes del `otro`");
+
+        // Las palabras reservadas no pueden ser nombres
+        for (prog, palabra, col) in [
+            ("var si = 1;", "si", 5),
+            ("var otro = 1;", "otro", 5),
+            ("para(var mientras = 0; 1 < 2; x = 1){ }", "mientras", 10),
+            ("funcion avanza() { }", "avanza", 9),
+            ("funcion f(a, gira) { }", "gira", 14),
+        ] {
+            let err = compile(prog).unwrap_err();
+            assert_eq!(
+                err.mensaje,
+                format!(
+                    "`{}` es una palabra reservada y no puede usarse como nombre",
+                    palabra
+                )
+            );
+            assert_eq!(err.span.col, col);
+        }
+        assert!(compile("si = 1;").is_err());
+
+        // Las variables de los sensores solo las modifica el intérprete
+        for prog in [
+            "radar = 99;",
+            "si(verdadero){ var radar = 0; }",
+            "municion_chica = 1;",
+            "var municion_grande = 1;",
+            "funcion f(municion_chica) { }",
+        ] {
+            let err = compile(prog).unwrap_err();
+            assert!(err.mensaje.ends_with("es una variable de solo lectura"));
+        }
+        assert!(compile("var x = radar + municion_chica + municion_grande;").is_ok());
+
+        let err = compile("dispara fuerte;").unwrap_err();
+        assert_eq!(err.esperado, vec!["`;`", "un arma (`chico` o `grande`)"]);
+
+        // Las funciones solo se definen en el nivel superior
+        assert!(compile("si(1 == 1){ funcion f() {} }").is_err());
+    }
 }
@@ -1,20 +1,36 @@
+use crate::valor::Valor;
 use std::collections::HashMap;
 
-#[derive(Debug)]
+/// Variables del programa. Cada bloque tiene su propio mapa; cada llamada a
+/// función abre además un marco, desde el cual solo son visibles los mapas del
+/// marco y el mapa global, no los de quien hizo la llamada.
+#[derive(Debug, Clone)]
 pub struct Scope {
-    map_stack: Vec<HashMap<String, isize>>,
+    map_stack: Vec<HashMap<String, Valor>>,
+    // Índice en `map_stack` del primer mapa de cada marco abierto
+    frames: Vec<usize>,
 }
 
 impl Scope {
     pub fn new() -> Self {
         let map = HashMap::new();
         let map_stack = vec![map];
-        Scope { map_stack }
+        Scope {
+            map_stack,
+            frames: vec![],
+        }
+    }
+
+    /// Índices de los mapas visibles, del más interno al global.
+    fn visible_maps(&self) -> impl Iterator<Item = usize> {
+        let inicio = self.frames.last().copied().unwrap_or(0);
+        let global = (inicio > 0).then_some(0);
+        (inicio..self.map_stack.len()).rev().chain(global)
     }
 
     /// Define un nuevo valor en el scope, independientemente de
     /// si estába definido previamente.
-    pub fn define_new_scope_var(&mut self, var_name: &str, value: isize) {
+    pub fn define_new_scope_var(&mut self, var_name: &str, value: Valor) {
         let mut current_map = self.map_stack.pop().unwrap();
         current_map.insert(var_name.into(), value);
         self.map_stack.push(current_map);
@@ -22,26 +38,38 @@ impl Scope {
 
     /// Busca un valor en scopes previos con el mismo nombre para asignarle el valor.
     /// Si no lo encuentra,regresa falso
-    pub fn set_scope_var(&mut self, var_name: &str, value: isize) -> bool {
-        let mut found = false;
-        'outer: for map in self.map_stack.iter_mut().rev() {
-            if let Some(_) = map.get(var_name.into()) {
-                map.insert(var_name.into(), value);
-                found = true;
-                break 'outer;
+    pub fn set_scope_var(&mut self, var_name: &str, value: Valor) -> bool {
+        match self
+            .visible_maps()
+            .find(|&i| self.map_stack[i].contains_key(var_name))
+        {
+            Some(i) => {
+                self.map_stack[i].insert(var_name.into(), value);
+                true
             }
+            None => false,
         }
-        found
     }
 
     /// Get `var_name`'s last defined value.
-    pub fn get_var_value(&self, var_name: &str) -> Option<isize> {
-        for map in self.map_stack.iter().rev() {
-            if let Some(value) = map.get(var_name.into()) {
-                return Some(*value);
+    pub fn get_var_value(&self, var_name: &str) -> Option<Valor> {
+        self.visible_maps()
+            .find_map(|i| self.map_stack[i].get(var_name).copied())
+    }
+
+    /// Variables visibles desde el scope actual con su valor, ordenadas por nombre.
+    /// Una variable redefinida en un scope interno oculta a la del scope externo.
+    pub fn visible_vars(&self) -> Vec<(String, Valor)> {
+        let mut vars: Vec<(String, Valor)> = Vec::new();
+        for i in self.visible_maps() {
+            for (name, value) in &self.map_stack[i] {
+                if !vars.iter().any(|(n, _)| n == name) {
+                    vars.push((name.clone(), *value));
+                }
             }
         }
-        None
+        vars.sort_by(|(a, _), (b, _)| a.cmp(b));
+        vars
     }
 
     pub fn drop(&mut self) {
@@ -50,6 +78,20 @@ impl Scope {
     pub fn add(&mut self) {
         self.map_stack.push(HashMap::new());
     }
+
+    /// Abre el marco de una llamada a función, con un mapa nuevo para sus
+    /// parámetros.
+    pub fn add_frame(&mut self) {
+        self.frames.push(self.map_stack.len());
+        self.add();
+    }
+
+    /// Cierra el marco actual junto con todos sus mapas.
+    pub fn drop_frame(&mut self) {
+        if let Some(inicio) = self.frames.pop() {
+            self.map_stack.truncate(inicio);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -65,16 +107,54 @@ mod test {
         //   var pepe = 23;
         //   pepe = 49;
         // }
-        scope.define_new_scope_var("pepe", 12);
+        scope.define_new_scope_var("pepe", Valor::Entero(12));
         scope.add();
-        scope.define_new_scope_var("pepe", 23);
+        scope.define_new_scope_var("pepe", Valor::Entero(23));
         let value = scope.get_var_value("pepe");
-        assert_eq!(value, Some(23));
-        scope.define_new_scope_var("pepe", 49);
+        assert_eq!(value, Some(Valor::Entero(23)));
+        scope.define_new_scope_var("pepe", Valor::Entero(49));
         let value = scope.get_var_value("pepe");
-        assert_eq!(value, Some(49));
+        assert_eq!(value, Some(Valor::Entero(49)));
+        scope.define_new_scope_var("i", Valor::Entero(5));
+        assert_eq!(
+            scope.visible_vars(),
+            vec![
+                ("i".to_string(), Valor::Entero(5)),
+                ("pepe".to_string(), Valor::Entero(49))
+            ]
+        );
         scope.drop();
         let value = scope.get_var_value("pepe");
-        assert_eq!(value, Some(12));
+        assert_eq!(value, Some(Valor::Entero(12)));
+    }
+
+    #[test]
+    pub fn test_scope_frames() {
+        let mut scope = Scope::new();
+        scope.define_new_scope_var("global", Valor::Entero(1));
+        scope.add();
+        scope.define_new_scope_var("local", Valor::Entero(2));
+
+        // Dentro de una función solo se ven sus variables y las globales
+        scope.add_frame();
+        scope.define_new_scope_var("param", Valor::Entero(3));
+        scope.add();
+        assert_eq!(scope.get_var_value("param"), Some(Valor::Entero(3)));
+        assert_eq!(scope.get_var_value("global"), Some(Valor::Entero(1)));
+        assert_eq!(scope.get_var_value("local"), None);
+        assert!(!scope.set_scope_var("local", Valor::Entero(5)));
+        assert!(scope.set_scope_var("global", Valor::Entero(4)));
+        assert_eq!(
+            scope.visible_vars(),
+            vec![
+                ("global".to_string(), Valor::Entero(4)),
+                ("param".to_string(), Valor::Entero(3))
+            ]
+        );
+
+        scope.drop_frame();
+        assert_eq!(scope.get_var_value("param"), None);
+        assert_eq!(scope.get_var_value("local"), Some(Valor::Entero(2)));
+        assert_eq!(scope.get_var_value("global"), Some(Valor::Entero(4)));
     }
 }
@@ -0,0 +1,160 @@
+use crate::error::ErrorTablero;
+use crate::tank_status::{next_pos, TankDirection, GRID_DIMMENSIONS};
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+
+/// Contenido de una casilla del tablero.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[wasm_bindgen]
+pub enum Casilla {
+    #[default]
+    Libre,
+    /// Detiene a los tanques, los disparos y el radar.
+    Muro,
+    /// Como un muro, pero se destruye al recibir un disparo.
+    Caja,
+    /// Un lago: bloquea igual que un muro, pero se dibuja distinto.
+    Agua,
+}
+
+impl Casilla {
+    /// Los tanques, los disparos y el radar no pasan de la casilla.
+    pub fn bloquea(self) -> bool {
+        self != Casilla::Libre
+    }
+}
+
+/// Terreno de una partida. Se puede escribir como texto, una fila por línea,
+/// con `.` (o un espacio) para las casillas libres, `#` para los muros, `c`
+/// para las cajas y `~` para el agua:
+///
+/// ```text
+/// ..#....
+/// ..#.c..
+/// ~~#....
+/// ```
+///
+/// Las filas y columnas que faltan quedan libres.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Tablero {
+    casillas: [[Casilla; GRID_DIMMENSIONS]; GRID_DIMMENSIONS],
+}
+
+impl Tablero {
+    /// Tablero sin obstáculos.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Contenido de la casilla `(i, j)`, o `None` fuera del tablero.
+    pub fn get(&self, (i, j): (usize, usize)) -> Option<Casilla> {
+        self.casillas.get(i)?.get(j).copied()
+    }
+
+    /// Cambia el contenido de la casilla `(i, j)`. Regresa `false` si está
+    /// fuera del tablero.
+    pub fn set(&mut self, (i, j): (usize, usize), casilla: Casilla) -> bool {
+        match self.casillas.get_mut(i).and_then(|fila| fila.get_mut(j)) {
+            Some(actual) => {
+                *actual = casilla;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Casillas libres de obstáculos frente a `pos` en la dirección `dir`,
+    /// hasta el borde del tablero.
+    pub fn radar(&self, mut pos: (usize, usize), dir: TankDirection) -> usize {
+        let mut libres = 0;
+        while let Some(sig) = next_pos(pos, dir) {
+            if self.get(sig).is_some_and(Casilla::bloquea) {
+                break;
+            }
+            libres += 1;
+            pos = sig;
+        }
+        libres
+    }
+}
+
+impl FromStr for Tablero {
+    type Err = ErrorTablero;
+
+    fn from_str(texto: &str) -> Result<Self, Self::Err> {
+        let mut tablero = Tablero::new();
+        for (i, fila) in texto.lines().enumerate() {
+            for (j, caracter) in fila.trim_end().chars().enumerate() {
+                let casilla = match caracter {
+                    '.' | ' ' => Casilla::Libre,
+                    '#' => Casilla::Muro,
+                    'c' => Casilla::Caja,
+                    '~' => Casilla::Agua,
+                    caracter => {
+                        return Err(ErrorTablero::Caracter {
+                            linea: i + 1,
+                            columna: j + 1,
+                            caracter,
+                        })
+                    }
+                };
+                if !tablero.set((i, j), casilla) {
+                    return Err(ErrorTablero::FueraDelTablero {
+                        linea: i + 1,
+                        columna: j + 1,
+                    });
+                }
+            }
+        }
+        Ok(tablero)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tablero() {
+        let tablero: Tablero = "..#\n~c.\n".parse().unwrap();
+        assert_eq!(tablero.get((0, 2)), Some(Casilla::Muro));
+        assert_eq!(tablero.get((1, 0)), Some(Casilla::Agua));
+        assert_eq!(tablero.get((1, 1)), Some(Casilla::Caja));
+        assert_eq!(tablero.get((5, 5)), Some(Casilla::Libre));
+        assert_eq!(tablero.get((GRID_DIMMENSIONS, 0)), None);
+
+        // El radar se detiene en muros, cajas y agua
+        assert_eq!(tablero.radar((0, 0), TankDirection::East), 1);
+        assert_eq!(tablero.radar((1, 0), TankDirection::East), 0);
+        assert_eq!(tablero.radar((2, 0), TankDirection::North), 0);
+        assert_eq!(
+            tablero.radar((5, 5), TankDirection::South),
+            GRID_DIMMENSIONS - 6
+        );
+
+        let err = "..\n.x".parse::<Tablero>().unwrap_err();
+        assert_eq!(
+            err,
+            ErrorTablero::Caracter {
+                linea: 2,
+                columna: 2,
+                caracter: 'x'
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "`x` no es una casilla; usa `.`, `#`, `c` o `~` (línea 2, columna 2)"
+        );
+        let fila = ".".repeat(GRID_DIMMENSIONS + 1);
+        assert_eq!(
+            fila.parse::<Tablero>().unwrap_err().posicion(),
+            (1, GRID_DIMMENSIONS + 1)
+        );
+        let filas = "\n".repeat(GRID_DIMMENSIONS) + "#";
+        assert!(filas.parse::<Tablero>().is_err());
+        // Los espacios al final de una fila se ignoran
+        assert!(format!("{}   ", ".".repeat(GRID_DIMMENSIONS))
+            .parse::<Tablero>()
+            .is_ok());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::ast::Arma;
 use wasm_bindgen::prelude::*;
 pub const GRID_DIMMENSIONS: usize = 11;
 
@@ -22,6 +23,8 @@ pub struct TankStatus {
     shot: bool,
     // if tank got shot
     got_shot: bool,
+    // if the last move was blocked
+    crashed: bool,
     ammo_small: usize,
     ammo_big: usize,
     current_interpreter_line: usize,
@@ -38,16 +41,19 @@ impl TankStatus {
     pub fn set_pos(&mut self, i: usize, j: usize) {
         self.pos = (i, j);
     }
+    /// Casillas frente al tanque hasta el borde del tablero, sin contar
+    /// obstáculos. Fuera del tablero nunca falla, aunque la lectura no
+    /// signifique nada.
     pub fn calc_radar(&self) -> isize {
         let (new_i, new_j) = self.get_pos();
         match self.get_dir() {
             TankDirection::West => new_j,
             TankDirection::North => new_i,
-            TankDirection::East => GRID_DIMMENSIONS - new_j - 1,
-            TankDirection::South => GRID_DIMMENSIONS - new_i - 1,
+            TankDirection::East => (GRID_DIMMENSIONS - 1).saturating_sub(new_j),
+            TankDirection::South => (GRID_DIMMENSIONS - 1).saturating_sub(new_i),
         }
         .try_into()
-        .unwrap()
+        .unwrap_or(isize::MAX)
     }
 
     pub fn set_shot(&mut self, shot: bool) {
@@ -64,6 +70,15 @@ impl TankStatus {
         self.got_shot
     }
 
+    #[wasm_bindgen(getter)]
+    pub fn crashed(&self) -> bool {
+        self.crashed
+    }
+
+    pub fn set_crashed(&mut self, crashed: bool) {
+        self.crashed = crashed;
+    }
+
     #[wasm_bindgen(getter)]
     pub fn health(&self) -> usize {
         self.health
@@ -73,12 +88,31 @@ impl TankStatus {
         self.got_shot = got_shot;
     }
 
+    #[wasm_bindgen(getter)]
+    pub fn ammo_small(&self) -> usize {
+        self.ammo_small
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn ammo_big(&self) -> usize {
+        self.ammo_big
+    }
+
+    pub fn set_ammo_small(&mut self, ammo: usize) {
+        self.ammo_small = ammo;
+    }
+
+    pub fn set_ammo_big(&mut self, ammo: usize) {
+        self.ammo_big = ammo;
+    }
+
     pub fn apply_damage(&mut self, damage: usize) -> usize {
         let new_health = self.health.saturating_sub(damage);
         self.health = new_health;
         new_health
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Self {
         Self {
             pos: (0, 0),
@@ -86,6 +120,7 @@ impl TankStatus {
             health: 100,
             shot: false,
             got_shot: false,
+            crashed: false,
             ammo_small: 10000,
             ammo_big: 100,
             current_interpreter_line: 0,
@@ -119,6 +154,38 @@ impl TankStatus {
     }
 }
 
+impl TankStatus {
+    /// Casilla de enfrente, si está dentro del tablero.
+    pub fn next_pos(&self) -> Option<(usize, usize)> {
+        next_pos(self.pos, self.direction)
+    }
+
+    /// Gasta una bala de `arma`. Regresa `false` si ya no quedaban.
+    pub fn spend_ammo(&mut self, arma: Arma) -> bool {
+        let ammo = match arma {
+            Arma::Chico => &mut self.ammo_small,
+            Arma::Grande => &mut self.ammo_big,
+        };
+        if *ammo == 0 {
+            return false;
+        }
+        *ammo -= 1;
+        true
+    }
+}
+
+/// Casilla vecina a `(i, j)` en la dirección `dir`, si está dentro del
+/// tablero.
+pub fn next_pos((i, j): (usize, usize), dir: TankDirection) -> Option<(usize, usize)> {
+    let (i, j) = match dir {
+        TankDirection::North => (i.checked_sub(1)?, j),
+        TankDirection::West => (i, j.checked_sub(1)?),
+        TankDirection::South => (i.checked_add(1)?, j),
+        TankDirection::East => (i, j.checked_add(1)?),
+    };
+    (i < GRID_DIMMENSIONS && j < GRID_DIMMENSIONS).then_some((i, j))
+}
+
 pub trait Position {
     fn get_pos(&self) -> (usize, usize);
 }
@@ -1,8 +1,11 @@
-use crate::interprete::{eval_logic, Interpreter};
+use crate::ast::{Arma, DirGiro};
+use crate::error::{ErrorInterprete, TipoBloque};
+use crate::interprete::{eval, Accion, Interpreter, Limites, StepResult};
 use crate::parser::ParserTanques;
 use crate::parser::*;
 use crate::scope::Scope;
 use crate::tank_status::{Position, TankDirection, TankStatus, GRID_DIMMENSIONS};
+use crate::valor::Valor;
 use pest::Parser;
 
 #[test]
@@ -12,26 +15,57 @@ pub fn test_expr() {
         )
         .unwrap();
 
-    let status = TankStatus::default();
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(3));
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("y"), Some(-1));
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("z"), Some(8));
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("w"), Some(1));
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("a"), Some(11));
-    let res = interprete.step_inst(&status);
+    let mut status = TankStatus::default();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(3)));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("y"), Some(Valor::Entero(-1)));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("z"), Some(Valor::Entero(8)));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("w"), Some(Valor::Entero(1)));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("a"), Some(Valor::Entero(11)));
+    let res = interprete.step_inst(&mut status);
     assert!(res.is_err());
+
+    // Módulo, menos unario y valor absoluto
+    let mut interprete = Interpreter::new(
+        "var x = 7 % 3;
+        var y = -x;
+        var z = -(x + 4) * 2;
+        var w = 10 + -x % 2;
+        var a = abs(y) + abs(-3 * 2);
+        var b = -7 % 3;
+        var c = 2 + 3 % 2 * 4;
+        var d = -abs(z);
+        var e = x -1;",
+    )
+    .unwrap();
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(1)));
+    assert_eq!(interprete.get_var_value("y"), Some(Valor::Entero(-1)));
+    assert_eq!(interprete.get_var_value("z"), Some(Valor::Entero(-10)));
+    assert_eq!(interprete.get_var_value("w"), Some(Valor::Entero(9)));
+    assert_eq!(interprete.get_var_value("a"), Some(Valor::Entero(7)));
+    assert_eq!(interprete.get_var_value("b"), Some(Valor::Entero(-1)));
+    assert_eq!(interprete.get_var_value("c"), Some(Valor::Entero(6)));
+    assert_eq!(interprete.get_var_value("d"), Some(Valor::Entero(-10)));
+    assert_eq!(interprete.get_var_value("e"), Some(Valor::Entero(0)));
 }
 
 #[test]
 pub fn test_expr_logic() {
     let eval_expr = |expr: &str, scope: &Scope| {
-        let pairs = ParserTanques::parse(Rule::expr_logic, expr).unwrap();
-        eval_logic(pairs, &scope).unwrap()
+        let pair = ParserTanques::parse(Rule::expr, expr)
+            .unwrap()
+            .next()
+            .unwrap();
+        let expr = build_expr(pair).unwrap();
+        match eval(&expr, scope).unwrap() {
+            Valor::Booleano(valor) => valor,
+            valor => panic!("{:?}", valor),
+        }
     };
 
     let mut scope = Scope::new();
@@ -60,30 +94,82 @@ pub fn test_expr_logic() {
     let val = eval_expr("1 <= 2", &scope);
     assert!(val);
 
-    scope.define_new_scope_var("x", 8);
+    scope.define_new_scope_var("x", Valor::Entero(8));
     let val = eval_expr("x == 8", &scope);
     assert!(val);
 
     let val = eval_expr("x != 8", &scope);
     assert!(!val);
 
-    scope.define_new_scope_var("y", 9);
+    scope.define_new_scope_var("y", Valor::Entero(9));
     let val = eval_expr("x < y", &scope);
     assert!(val);
 
     let val = eval_expr("x == y", &scope);
     assert!(!val);
+
+    assert!(eval_expr("verdadero", &scope));
+    assert!(!eval_expr("falso", &scope));
+    assert!(eval_expr("!falso", &scope));
+    assert!(!eval_expr("!!falso", &scope));
+    assert!(eval_expr("no x == y", &scope));
+    assert!(eval_expr("!(x == y)", &scope));
+    assert!(!eval_expr("no (x < y || falso)", &scope));
+    assert!(eval_expr("x < y && !falso", &scope));
+    // La negación solo aplica al término que le sigue
+    assert!(!eval_expr("no x < y && falso", &scope));
+    assert!(eval_expr("no x > y || falso", &scope));
+
+    // Nombres de variable que empiezan como una palabra reservada
+    scope.define_new_scope_var("nota", Valor::Entero(1));
+    scope.define_new_scope_var("falsos", Valor::Entero(2));
+    assert!(eval_expr("nota < falsos", &scope));
+    assert!(eval_expr("no nota == falsos", &scope));
+
+    // Precedencia: `&&` liga más fuerte que `||` y ambos asocian a la
+    // izquierda, para todas las combinaciones de valores
+    let lit = |b: bool| if b { "verdadero" } else { "falso" };
+    for a in [false, true] {
+        for b in [false, true] {
+            for c in [false, true] {
+                let (la, lb, lc) = (lit(a), lit(b), lit(c));
+                let casos = [
+                    (format!("{la} || {lb} && {lc}"), a || (b && c)),
+                    (format!("{la} && {lb} || {lc}"), (a && b) || c),
+                    (format!("({la} || {lb}) && {lc}"), (a || b) && c),
+                    (format!("{la} && ({lb} || {lc})"), a && (b || c)),
+                    (format!("!{la} || {lb} && {lc}"), !a || (b && c)),
+                    (format!("!({la} || {lb}) && {lc}"), !(a || b) && c),
+                    (format!("{la} && {lb} && {lc}"), a && b && c),
+                    (format!("{la} || {lb} || {lc}"), a || b || c),
+                ];
+                for (expr, esperado) in casos {
+                    assert_eq!(eval_expr(&expr, &scope), esperado, "{expr}");
+                }
+            }
+        }
+    }
+    // Las comparaciones ligan más fuerte que los operadores lógicos
+    assert!(eval_expr("x == 8 || x == 9 && y == 0", &scope));
+    assert!(!eval_expr("(x == 8 || x == 9) && y == 0", &scope));
+
+    // Corto circuito: el lado derecho no se evalúa si el izquierdo decide
+    scope.define_new_scope_var("cero", Valor::Entero(0));
+    assert!(!eval_expr("cero != 0 && 10 / cero > 1", &scope));
+    assert!(eval_expr("cero == 0 || 10 / cero > 1", &scope));
+    assert!(eval_expr("falso && z == 1 || verdadero", &scope));
+    assert!(eval_expr("x == 8 || z == 1 && 10 / cero > 1", &scope));
 }
 
 #[test]
 fn test_asig() {
     let mut interprete = Interpreter::new("var x = 1;x = x + 1; y = 2;").unwrap();
-    let status = TankStatus::default();
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(1));
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(2));
-    let res = interprete.step_inst(&status);
+    let mut status = TankStatus::default();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(1)));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(2)));
+    let res = interprete.step_inst(&mut status);
     assert!(res.is_err());
 }
 
@@ -99,22 +185,38 @@ fn test_si() {
             x = 10;",
     )
     .unwrap();
-    let status = TankStatus::default();
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(1));
+    let mut status = TankStatus::default();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(1)));
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(3));
+    // Evaluar la condición es un paso propio
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Condicion { valor: true }
+    );
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(1)));
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(7));
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Asignacion {
+            nombre: "x".into(),
+            valor: Valor::Entero(3)
+        }
+    );
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("y"), Some(2));
-    assert_eq!(interprete.get_var_value("x"), Some(7));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(7)));
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(10));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("y"), Some(Valor::Entero(2)));
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(7)));
+
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(10)));
+
+    // Al terminar el programa se indica en cada paso
+    assert_eq!(interprete.step_inst(&mut status).unwrap(), StepResult::Fin);
+    assert_eq!(interprete.step_inst(&mut status).unwrap(), StepResult::Fin);
 
     let mut interprete = Interpreter::new(
         "var x = 1; 
@@ -129,20 +231,24 @@ fn test_si() {
     )
     .unwrap();
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(1));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(1)));
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(3));
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(3)));
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(100));
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(100)));
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(100));
+    // Salir de los dos bloques no cuenta como paso
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("y"), Some(Valor::Entero(2)));
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(100)));
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(10));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(10)));
 
     let mut interprete = Interpreter::new(
         "var x = 2; 
@@ -154,9 +260,13 @@ fn test_si() {
         var y = 2;",
     )
     .unwrap();
-    interprete.step_inst(&status).unwrap();
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(12));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Condicion { valor: false }
+    );
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(12)));
 
     let mut interprete = Interpreter::new(
         "var x = 1; 
@@ -168,10 +278,57 @@ fn test_si() {
         var y = 2;",
     )
     .unwrap();
-    interprete.step_inst(&status).unwrap();
-    interprete.step_inst(&status).unwrap();
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(3));
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(3)));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(3)));
+    assert_eq!(interprete.get_var_value("y"), Some(Valor::Entero(2)));
+}
+
+#[test]
+fn test_sino_si() {
+    let ejecuta = |x: isize, otro: &str| {
+        let mut interprete = Interpreter::new(&format!(
+            "var x = {};
+            var r = 0;
+            si(x == 1){{
+                r = 10;
+            }} sino si(x == 2){{
+                r = 20;
+            }} sino si(x == 3){{
+                r = 30;
+            }} {}",
+            x, otro
+        ))
+        .unwrap();
+        let mut status = TankStatus::default();
+        let mut condiciones = vec![];
+        loop {
+            match interprete.step_inst(&mut status).unwrap() {
+                StepResult::Fin => break,
+                StepResult::Condicion { valor } => condiciones.push(valor),
+                _ => {}
+            }
+        }
+        match interprete.get_var_value("r") {
+            Some(Valor::Entero(r)) => (r, condiciones),
+            r => panic!("{:?}", r),
+        }
+    };
+
+    // Cada condición se evalúa en su propio paso, solo hasta la primera
+    // verdadera
+    let otro = "otro { r = 40; }";
+    assert_eq!(ejecuta(1, otro), (10, vec![true]));
+    assert_eq!(ejecuta(2, otro), (20, vec![false, true]));
+    assert_eq!(ejecuta(3, otro), (30, vec![false, false, true]));
+    assert_eq!(ejecuta(4, otro), (40, vec![false, false, false]));
+
+    // Sin `otro` ninguna rama se ejecuta
+    assert_eq!(ejecuta(3, ""), (30, vec![false, false, true]));
+    assert_eq!(ejecuta(4, ""), (0, vec![false, false, false]));
 }
 
 #[test]
@@ -184,26 +341,583 @@ fn test_mientras() {
         var y = 10;",
     )
     .unwrap();
-    let status = TankStatus::default();
+    let mut status = TankStatus::default();
 
     assert_eq!(interprete.get_var_value("x"), None);
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(0));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(0)));
+
+    for x in 1..=3 {
+        assert_eq!(
+            interprete.step_inst(&mut status).unwrap(),
+            StepResult::Condicion { valor: true }
+        );
+        interprete.step_inst(&mut status).unwrap();
+        assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(x)));
+    }
+    assert_eq!(interprete.get_var_value("y"), None);
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(1));
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Condicion { valor: false }
+    );
+    assert_eq!(interprete.get_var_value("y"), None);
+
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(3)));
+    assert_eq!(interprete.get_var_value("y"), Some(Valor::Entero(10)));
+    assert_eq!(interprete.step_inst(&mut status).unwrap(), StepResult::Fin);
+
+    let mut interprete = Interpreter::new(
+        "var x = 0;
+        mientras(verdadero){
+            x = x + 1;
+            si(no x < 5){ rompe; }
+        }
+        mientras(falso){ x = 100; }",
+    )
+    .unwrap();
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(5)));
+}
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(2));
+#[test]
+fn test_mientras_scope() {
+    // La condición no ve las variables declaradas en el cuerpo, igual que en
+    // un `para`; tampoco al llegar con `continua` ni al llamar una función
+    let mut interprete = Interpreter::new(
+        "funcion menor(a, b) { regresa a < b; }
+        var x = 0;
+        var n = 0;
+        mientras(x < 3){
+            n = n + 1;
+            x = x + 1;
+            var x = 10;
+        }
+        var y = 0;
+        mientras(menor(y, 3)){
+            y = y + 1;
+            var y = 10;
+            si(verdadero){ continua; }
+        }",
+    )
+    .unwrap();
+    let mut status = TankStatus::default();
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    assert_eq!(interprete.get_var_value("n"), Some(Valor::Entero(3)));
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(3)));
+    assert_eq!(interprete.get_var_value("y"), Some(Valor::Entero(3)));
+    assert_eq!(interprete.variables().len(), 7);
+}
+
+#[test]
+fn test_para() {
+    let mut interprete = Interpreter::new(
+        "var suma = 0;
+        para(var i = 0; i < 3; i = i + 1){
+            var y = i;
+            suma = suma + y;
+        }
+        var fin = suma;",
+    )
+    .unwrap();
+    let mut status = TankStatus::default();
+    interprete.step_inst(&mut status).unwrap();
+
+    // La inicialización es el primer paso del ciclo
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Asignacion {
+            nombre: "i".into(),
+            valor: Valor::Entero(0)
+        }
+    );
+    for i in 0..3 {
+        assert_eq!(
+            interprete.step_inst(&mut status).unwrap(),
+            StepResult::Condicion { valor: true }
+        );
+        interprete.step_inst(&mut status).unwrap();
+        assert_eq!(interprete.get_var_value("y"), Some(Valor::Entero(i)));
+        interprete.step_inst(&mut status).unwrap();
+        // La actualización es un paso propio, fuera del scope del cuerpo
+        assert_eq!(
+            interprete.step_inst(&mut status).unwrap(),
+            StepResult::Asignacion {
+                nombre: "i".into(),
+                valor: Valor::Entero(i + 1)
+            }
+        );
+        assert_eq!(interprete.get_var_value("y"), None);
+    }
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Condicion { valor: false }
+    );
+    assert_eq!(interprete.get_var_value("i"), None);
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("fin"), Some(Valor::Entero(3)));
+    assert_eq!(interprete.step_inst(&mut status).unwrap(), StepResult::Fin);
+
+    // La inicialización puede asignar una variable existente
+    let mut interprete = Interpreter::new(
+        "var j = 5;
+        para(j = 0; j < 2; j = j + 1){ avanza; }",
+    )
+    .unwrap();
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    assert_eq!(interprete.get_var_value("j"), Some(Valor::Entero(2)));
+
+    // Las tres partes del ciclo pueden llamar a funciones
+    let mut interprete = Interpreter::new(
+        "funcion dos() { regresa 2; }
+        var n = 0;
+        para(var i = dos(); i < dos() * 3; i = i + dos()){ n = n + 1; }",
+    )
+    .unwrap();
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    assert_eq!(interprete.get_var_value("n"), Some(Valor::Entero(2)));
+
+    // Salir de una función desde el cuerpo deja el scope como estaba
+    let mut interprete = Interpreter::new(
+        "funcion busca(n) {
+            para(var i = 0; i < 10; i = i + 1){
+                si(i * i >= n){ regresa i; }
+            }
+            regresa 0 - 1;
+        }
+        var x = busca(5);
+        var z = x;",
+    )
+    .unwrap();
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    assert_eq!(interprete.get_var_value("z"), Some(Valor::Entero(3)));
+    assert_eq!(interprete.variables().len(), 6);
+
+    let mut interprete = Interpreter::new(
+        "para(var i = 3; i >= 0; i = i - 1){
+            var x = 6 / i;
+        }",
+    )
+    .unwrap();
+    let err = loop {
+        if let Err(err) = interprete.step_inst(&mut status) {
+            break err;
+        }
+    };
+    assert!(matches!(err.error, ErrorInterprete::DivisionEntreCero));
+    assert_eq!(err.traza[0].tipo, TipoBloque::Para);
+}
+
+#[test]
+fn test_repite() {
+    let mut interprete = Interpreter::new(
+        "var n = 3;
+        var x = 0;
+        repite n {
+            var y = x;
+            x = y + 1;
+            n = 10;
+        }
+        var z = x;",
+    )
+    .unwrap();
+    let mut status = TankStatus::default();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(3));
+    // El número de vueltas se evalúa una sola vez
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Repite { veces: 3 }
+    );
+    for x in 1..=3 {
+        interprete.step_inst(&mut status).unwrap();
+        assert_eq!(interprete.get_var_value("y"), Some(Valor::Entero(x - 1)));
+        interprete.step_inst(&mut status).unwrap();
+        assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(x)));
+        interprete.step_inst(&mut status).unwrap();
+    }
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Asignacion {
+            nombre: "z".into(),
+            valor: Valor::Entero(3)
+        }
+    );
     assert_eq!(interprete.get_var_value("y"), None);
+    assert_eq!(interprete.step_inst(&mut status).unwrap(), StepResult::Fin);
+
+    // Con cero o menos vueltas no se entra al bloque
+    let mut interprete = Interpreter::new("repite 0 - 2 { avanza; } gira izquierda;").unwrap();
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Repite { veces: 0 }
+    );
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Accion(Accion::Gira(DirGiro::Izquierda))
+    );
+
+    // Un bloque vacío regresa el control al agotar el combustible del paso
+    let mut interprete = Interpreter::new("repite 1000 { } avanza;").unwrap();
+    interprete.set_limites(Limites {
+        por_paso: 100,
+        ..Limites::default()
+    });
+    interprete.step_inst(&mut status).unwrap();
+    for _ in 0..10 {
+        assert_eq!(
+            interprete.step_inst(&mut status).unwrap(),
+            StepResult::Pausa
+        );
+    }
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Accion(Accion::Avanza)
+    );
+}
+
+#[test]
+fn test_rompe_continua() {
+    let mut status = TankStatus::default();
+    let mut interprete = Interpreter::new(
+        "var x = 0;
+        mientras(1 == 1){
+            var y = x;
+            x = y + 1;
+            si(x == 3){
+                rompe;
+            }
+        }
+        var fin = x;",
+    )
+    .unwrap();
+    let mut pasos = vec![];
+    loop {
+        match interprete.step_inst(&mut status).unwrap() {
+            StepResult::Fin => break,
+            paso => pasos.push(paso),
+        }
+    }
+    // `rompe` sale del `si` y del ciclo, sin evaluar de nuevo la condición
+    let ultimos = &pasos[pasos.len() - 3..];
+    assert_eq!(ultimos[0], StepResult::Condicion { valor: true });
+    assert_eq!(ultimos[1], StepResult::Salto);
+    assert_eq!(
+        ultimos[2],
+        StepResult::Asignacion {
+            nombre: "fin".into(),
+            valor: Valor::Entero(3)
+        }
+    );
+    assert_eq!(
+        interprete.variables(),
+        vec![
+            ("choque".to_string(), Valor::Booleano(false)),
+            ("fin".to_string(), Valor::Entero(3)),
+            ("municion_chica".to_string(), Valor::Entero(10000)),
+            ("municion_grande".to_string(), Valor::Entero(100)),
+            ("radar".to_string(), Valor::Entero(0)),
+            ("x".to_string(), Valor::Entero(3))
+        ]
+    );
+
+    // `continua` en un `mientras` evalúa de nuevo la condición
+    let mut interprete = Interpreter::new(
+        "var x = 0;
+        mientras(x < 2){
+            x = x + 1;
+            continua;
+            avanza;
+        }",
+    )
+    .unwrap();
+    let mut pasos = vec![];
+    loop {
+        match interprete.step_inst(&mut status).unwrap() {
+            StepResult::Fin => break,
+            StepResult::Asignacion { .. } => pasos.push("asig"),
+            StepResult::Condicion { valor: true } => pasos.push("verdadero"),
+            StepResult::Condicion { valor: false } => pasos.push("falso"),
+            StepResult::Salto => pasos.push("salto"),
+            paso => panic!("{:?}", paso),
+        }
+    }
+    assert_eq!(
+        pasos,
+        vec![
+            "asig",
+            "verdadero",
+            "asig",
+            "salto",
+            "verdadero",
+            "asig",
+            "salto",
+            "falso"
+        ]
+    );
+
+    // `continua` en un `para` ejecuta la actualización
+    let mut interprete = Interpreter::new(
+        "var suma = 0;
+        para(var i = 0; i < 10; i = i + 1){
+            si(i / 2 * 2 == i){
+                continua;
+            }
+            suma = suma + i;
+        }",
+    )
+    .unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Salto
+    );
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Asignacion {
+            nombre: "i".into(),
+            valor: Valor::Entero(1)
+        }
+    );
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    assert_eq!(interprete.get_var_value("suma"), Some(Valor::Entero(25)));
+    assert_eq!(interprete.variables().len(), 5);
+
+    // Solo se sale del ciclo más cercano
+    let mut interprete = Interpreter::new(
+        "var n = 0;
+        repite 3 {
+            repite 5 {
+                n = n + 1;
+                rompe;
+            }
+            repite 2 {
+                continua;
+                n = 100;
+            }
+        }",
+    )
+    .unwrap();
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    assert_eq!(interprete.get_var_value("n"), Some(Valor::Entero(3)));
+}
+
+#[test]
+fn test_funciones() {
+    let mut status = TankStatus::default();
+
+    // Procedimiento usado como instrucción
+    let mut interprete = Interpreter::new(
+        "funcion vuelta() {
+            gira derecha;
+            gira derecha;
+        }
+        vuelta();
+        avanza;",
+    )
+    .unwrap();
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Llamada {
+            nombre: "vuelta".into()
+        }
+    );
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(status.get_dir(), TankDirection::South);
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Accion(Accion::Avanza)
+    );
+    assert_eq!(interprete.step_inst(&mut status).unwrap(), StepResult::Fin);
+
+    // Parámetros y valor de regreso dentro de una expresión
+    let mut interprete = Interpreter::new(
+        "var y = 10;
+        var x = suma(1, 2) * 2;
+        funcion suma(a, b) {
+            var c = a + b;
+            regresa c;
+        }",
+    )
+    .unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("a"), Some(Valor::Entero(1)));
+    assert_eq!(interprete.get_var_value("b"), Some(Valor::Entero(2)));
+    // Las variables globales son visibles dentro de la función
+    assert_eq!(interprete.get_var_value("y"), Some(Valor::Entero(10)));
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Regreso {
+            valor: Some(Valor::Entero(3))
+        }
+    );
+    assert_eq!(interprete.get_var_value("a"), None);
+    assert_eq!(interprete.get_var_value("c"), None);
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Asignacion {
+            nombre: "x".into(),
+            valor: Valor::Entero(6)
+        }
+    );
+
+    // Recursión
+    let mut interprete = Interpreter::new(
+        "funcion fact(n) {
+            si(n <= 1){
+                regresa 1;
+            }
+            regresa n * fact(n - 1);
+        }
+        var x = fact(5);",
+    )
+    .unwrap();
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(120)));
+
+    // Las variables leídas antes de la llamada conservan su valor
+    let mut interprete = Interpreter::new(
+        "var g = 1;
+        funcion incrementa() {
+            g = g + 1;
+            regresa 0;
+        }
+        var x = g * 10 + incrementa() + g;",
+    )
+    .unwrap();
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    assert_eq!(interprete.get_var_value("x"), Some(Valor::Entero(12)));
+    assert_eq!(interprete.get_var_value("g"), Some(Valor::Entero(2)));
+
+    // Una condición que llama a una función se evalúa de nuevo en cada vuelta
+    let mut interprete = Interpreter::new(
+        "var x = 0;
+        funcion menor(a, b) {
+            si(a < b){ regresa 1; }
+            regresa 0;
+        }
+        mientras(menor(x, 3) == 1){
+            x = x + 1;
+        }
+        var fin = x;",
+    )
+    .unwrap();
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    assert_eq!(interprete.get_var_value("fin"), Some(Valor::Entero(3)));
+
+    // El corto circuito evita llamadas cuyo resultado no hace falta
+    let mut interprete = Interpreter::new(
+        "var g = 0;
+        funcion toca() {
+            g = g + 1;
+            regresa 1;
+        }
+        si(g == 1 && toca() == 1){ avanza; }
+        si(g == 0 || toca() == 1){ avanza; }
+        si(g == 0 && toca() == 1){ avanza; }",
+    )
+    .unwrap();
+    let mut llamadas = 0;
+    loop {
+        match interprete.step_inst(&mut status).unwrap() {
+            StepResult::Fin => break,
+            StepResult::Llamada { .. } => llamadas += 1,
+            _ => {}
+        }
+    }
+    assert_eq!(llamadas, 1);
+    assert_eq!(interprete.get_var_value("g"), Some(Valor::Entero(1)));
+}
+
+#[test]
+fn test_errores_funciones() {
+    let mut status = TankStatus::default();
+    let ejecuta = |interprete: &mut Interpreter, status: &mut TankStatus| loop {
+        match interprete.step_inst(status) {
+            Ok(StepResult::Fin) => panic!("el programa terminó sin error"),
+            Ok(_) => {}
+            Err(err) => break err,
+        }
+    };
+
+    // Una función no ve las variables locales de quien la llama
+    let mut interprete = Interpreter::new(
+        "funcion f() {
+            avanza;
+            var y = x;
+        }
+        si(1 == 1){
+            var x = 1;
+            f();
+        }",
+    )
+    .unwrap();
+    let err = ejecuta(&mut interprete, &mut status);
+    assert!(matches!(err.error, ErrorInterprete::VarNoDecl(ref var) if var == "x"));
+    let traza: Vec<_> = err
+        .traza
+        .iter()
+        .map(|marco| (marco.tipo, marco.span.line))
+        .collect();
+    assert_eq!(traza, vec![(TipoBloque::Funcion, 7), (TipoBloque::Si, 5)]);
+    assert!(err
+        .to_string()
+        .contains("dentro de la llamada en la línea 7"));
+
+    let mut interprete = Interpreter::new("funcion f() { f(); } f();").unwrap();
+    interprete.set_limites(Limites {
+        profundidad: 10,
+        ..Limites::default()
+    });
+    let err = ejecuta(&mut interprete, &mut status);
+    assert!(matches!(
+        err.error,
+        ErrorInterprete::ProfundidadExcedida(10)
+    ));
+    assert_eq!(err.traza.len(), 10);
+    // La recursión no llena el mensaje con la misma línea
+    assert_eq!(
+        err.to_string(),
+        "error en la línea 1, columna 15: Se excedió el máximo de 10 llamadas anidadas\n  \
+         dentro de la llamada en la línea 1 (repetido 10 veces)"
+    );
+
+    let mut interprete = Interpreter::new(
+        "funcion cuenta(n) {
+            si(n > 0) {
+                cuenta(n - 1);
+            }
+        }
+        repite 2 {
+            cuenta(1000);
+        }",
+    )
+    .unwrap();
+    let err = ejecuta(&mut interprete, &mut status);
+    assert!(matches!(err.error, ErrorInterprete::ProfundidadExcedida(_)));
+    assert!(err.traza.len() > 100);
+    assert!(err.to_string().ends_with(
+        "\n  dentro de `si` en la línea 2\n  \
+         dentro de la llamada en la línea 3\n  \
+         (los 2 bloques anteriores se repiten 99 veces)\n  \
+         dentro de `si` en la línea 2\n  \
+         dentro de la llamada en la línea 7\n  \
+         dentro de `repite` en la línea 6"
+    ));
 
-    interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x"), Some(3));
-    assert_eq!(interprete.get_var_value("y"), Some(10));
+    let mut interprete = Interpreter::new("funcion f() { avanza; } var x = f() + 1;").unwrap();
+    let err = ejecuta(&mut interprete, &mut status);
+    assert!(matches!(err.error, ErrorInterprete::SinValor(ref f) if f == "f"));
+    assert_eq!((err.span.col, err.span.end_col), (33, 36));
 }
 
 #[test]
@@ -224,38 +938,95 @@ fn test_gira() {
     let mut status = TankStatus::default();
     status.set_dir(TankDirection::North);
 
-    let new_status = interprete.step_inst(&status).unwrap();
-    assert_eq!(new_status.get_dir(), TankDirection::East);
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(status.get_dir(), TankDirection::East);
 
-    let new_status = interprete.step_inst(&new_status).unwrap();
-    assert_eq!(new_status.get_dir(), TankDirection::South);
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(status.get_dir(), TankDirection::South);
 
-    let new_status = interprete.step_inst(&new_status).unwrap();
-    assert_eq!(new_status.get_dir(), TankDirection::West);
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(status.get_dir(), TankDirection::West);
 
-    let new_status = interprete.step_inst(&new_status).unwrap();
-    assert_eq!(new_status.get_dir(), TankDirection::North);
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(status.get_dir(), TankDirection::North);
 
-    let new_status = interprete.step_inst(&new_status).unwrap();
-    assert_eq!(new_status.get_dir(), TankDirection::West);
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(status.get_dir(), TankDirection::West);
 
-    let new_status = interprete.step_inst(&new_status).unwrap();
-    assert_eq!(new_status.get_dir(), TankDirection::South);
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(status.get_dir(), TankDirection::South);
 
-    let new_status = interprete.step_inst(&new_status).unwrap();
-    assert_eq!(new_status.get_dir(), TankDirection::East);
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(status.get_dir(), TankDirection::East);
 
-    let new_status = interprete.step_inst(&new_status).unwrap();
-    assert_eq!(new_status.get_dir(), TankDirection::North);
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(status.get_dir(), TankDirection::North);
 }
 
 #[test]
 fn test_dispara() {
     let mut interprete = Interpreter::new("dispara;").unwrap();
-    let status = TankStatus::default();
+    let mut status = TankStatus::default();
+    assert!(!status.shot());
+    interprete.step_inst(&mut status).unwrap();
+    assert!(status.shot());
+
+    let mut interprete = Interpreter::new(
+        "dispara grande;
+        dispara chico;
+        dispara grande;
+        var balas = municion_chica;",
+    )
+    .unwrap();
+    let mut status = TankStatus::default();
+    status.set_ammo_big(1);
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Accion(Accion::Dispara(Arma::Grande))
+    );
+    assert_eq!(status.ammo_big(), 0);
+    assert_eq!(
+        interprete.get_var_value("municion_grande"),
+        Some(Valor::Entero(0))
+    );
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Accion(Accion::Dispara(Arma::Chico))
+    );
+    assert_eq!(status.ammo_small(), 9999);
+
+    // Sin munición el tanque pierde el turno sin disparar
+    status.set_shot(false);
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Accion(Accion::SinMunicion(Arma::Grande))
+    );
     assert!(!status.shot());
-    let new_status = interprete.step_inst(&status).unwrap();
-    assert!(new_status.shot())
+
+    // Los cambios hechos fuera del programa se ven en el siguiente paso
+    status.set_ammo_small(3);
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("balas"), Some(Valor::Entero(3)));
+}
+
+#[test]
+fn test_choque() {
+    let mut interprete = Interpreter::new(
+        "avanza;
+        var a = choque;
+        gira derecha;
+        avanza;
+        var b = choque;",
+    )
+    .unwrap();
+    let mut status = TankStatus::default();
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    // En el borde el tanque no se mueve
+    assert_eq!(interprete.get_var_value("a"), Some(Valor::Booleano(true)));
+    assert_eq!(interprete.get_var_value("b"), Some(Valor::Booleano(false)));
+    assert_eq!(status.get_pos(), (0, 1));
+    assert!(!status.crashed());
+    assert!(compile("choque = falso;").is_err());
 }
 
 #[test]
@@ -284,34 +1055,34 @@ fn test_avanza() {
 
     let mut status = TankStatus::default();
     status.set_dir(TankDirection::East);
-    let mut status = interprete.step_inst(&mut status).unwrap();
-    let mut status = interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
     assert_eq!(status.get_pos(), (0, 2));
-    let mut status = interprete.step_inst(&mut status).unwrap();
-    let mut status = interprete.step_inst(&mut status).unwrap();
-    let mut status = interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
     assert_eq!(status.get_pos(), (2, 2));
-    let mut status = interprete.step_inst(&mut status).unwrap();
-    let mut status = interprete.step_inst(&mut status).unwrap();
-    let mut status = interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
     assert_eq!(status.get_pos(), (2, 0));
-    let mut status = interprete.step_inst(&mut status).unwrap();
-    let mut status = interprete.step_inst(&mut status).unwrap();
-    let mut status = interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
     assert_eq!(status.get_pos(), (0, 0));
-    let mut status = interprete.step_inst(&mut status).unwrap();
-    let mut status = interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
     assert_eq!(status.get_pos(), (0, 0));
 
     status.set_dir(TankDirection::South);
     status.set_pos(GRID_DIMMENSIONS - 1, GRID_DIMMENSIONS - 1);
-    let mut status = interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
     assert_eq!(
         status.get_pos(),
         (GRID_DIMMENSIONS - 1, GRID_DIMMENSIONS - 1)
     );
-    let mut status = interprete.step_inst(&mut status).unwrap();
-    let status = interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
     assert_eq!(
         status.get_pos(),
         (GRID_DIMMENSIONS - 1, GRID_DIMMENSIONS - 1)
@@ -331,12 +1102,12 @@ fn test_comment() {
 
     let mut tank_status = TankStatus::default();
     tank_status.set_dir(TankDirection::East);
-    let tank_status = interprete.step_inst(&tank_status).unwrap();
+    interprete.step_inst(&mut tank_status).unwrap();
     assert_eq!(tank_status.get_pos(), (0, 1));
-    let tank_status = interprete.step_inst(&tank_status).unwrap();
+    interprete.step_inst(&mut tank_status).unwrap();
     assert_eq!(tank_status.get_dir(), TankDirection::South);
-    interprete.step_inst(&tank_status).unwrap();
-    interprete.step_inst(&tank_status).unwrap();
+    interprete.step_inst(&mut tank_status).unwrap();
+    interprete.step_inst(&mut tank_status).unwrap();
 }
 
 #[test]
@@ -366,21 +1137,409 @@ fn test_radar() {
     )
     .unwrap();
 
-    let status = interprete.step_inst(&status).unwrap();
-    let status = interprete.step_inst(&status).unwrap();
-    let status = interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x").unwrap(), 1);
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x").unwrap(), Valor::Entero(1));
+
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x").unwrap(), Valor::Entero(5));
+
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x").unwrap(), Valor::Entero(9));
+
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.get_var_value("x").unwrap(), Valor::Entero(7));
+}
+
+#[test]
+fn test_interprete_propio() {
+    fn assert_send_static<T: Send + 'static>() {}
+    assert_send_static::<Interpreter>();
+
+    let prog = String::from("var x = 1; x = x + 1;");
+    let mut interprete = Box::new(Interpreter::new(&prog).unwrap());
+    drop(prog);
+
+    let mut status = TankStatus::default();
+    let handle = std::thread::spawn(move || {
+        interprete.step_inst(&mut status).unwrap();
+        interprete.step_inst(&mut status).unwrap();
+        interprete.get_var_value("x")
+    });
+    assert_eq!(handle.join().unwrap(), Some(Valor::Entero(2)));
+
+    let program = compile("var x = 3;").unwrap();
+    let mut a = Interpreter::from_program(program.clone());
+    let b = Interpreter::from_program(program);
+    a.step_inst(&mut status).unwrap();
+    assert_eq!(a.get_var_value("x"), Some(Valor::Entero(3)));
+    assert_eq!(b.get_var_value("x"), None);
+}
+
+#[test]
+fn test_error_ubicacion() {
+    let mut interprete = Interpreter::new(
+        "var x = 0;
+        mientras(x < 3){
+            si(x == 1){
+                x = x + z;
+            }
+            x = x + 1;
+        }",
+    )
+    .unwrap();
+    let mut status = TankStatus::default();
+    for _ in 0..6 {
+        interprete.step_inst(&mut status).unwrap();
+    }
+    let err = interprete.step_inst(&mut status).unwrap_err();
+    assert!(matches!(err.error, ErrorInterprete::VarNoDecl(ref var) if var == "z"));
+    assert_eq!((err.span.line, err.span.col), (4, 25));
+    assert_eq!((err.span.end_line, err.span.end_col), (4, 26));
+    let traza: Vec<_> = err
+        .traza
+        .iter()
+        .map(|marco| (marco.tipo, marco.span.line))
+        .collect();
+    assert_eq!(traza, vec![(TipoBloque::Si, 3), (TipoBloque::Mientras, 2)]);
+    assert_eq!(
+        err.to_string(),
+        "error en la línea 4, columna 25: Variable no declarada previamente: z\n  \
+         dentro de `si` en la línea 3\n  \
+         dentro de `mientras` en la línea 2"
+    );
+
+    let mut interprete = Interpreter::new("var x = 1;\nx = 2;\ny = 3;").unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    let err = interprete.step_inst(&mut status).unwrap_err();
+    assert_eq!((err.span.line, err.span.col), (3, 1));
+    assert!(err.traza.is_empty());
+}
+
+#[test]
+fn test_valores_logicos() {
+    let mut status = TankStatus::default();
+
+    let mut interprete = Interpreter::new(
+        "var lleno = radar < 2;
+        si(lleno){ avanza; }",
+    )
+    .unwrap();
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Asignacion {
+            nombre: "lleno".into(),
+            valor: Valor::Booleano(true)
+        }
+    );
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Condicion { valor: true }
+    );
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Accion(Accion::Avanza)
+    );
+
+    let mut interprete = Interpreter::new(
+        "var b = verdadero;
+        b = no b;
+        var c = b == falso;
+        var d = (1 < 2) && c != b;
+        funcion par(n) {
+            regresa n % 2 == 0;
+        }
+        var e = par(4) || par(3);
+        var f = 0;
+        mientras(no par(f) || f < 3){
+            f = f + 1;
+        }",
+    )
+    .unwrap();
+    while interprete.step_inst(&mut status).unwrap() != StepResult::Fin {}
+    assert_eq!(interprete.get_var_value("b"), Some(Valor::Booleano(false)));
+    assert_eq!(interprete.get_var_value("c"), Some(Valor::Booleano(true)));
+    assert_eq!(interprete.get_var_value("d"), Some(Valor::Booleano(true)));
+    assert_eq!(interprete.get_var_value("e"), Some(Valor::Booleano(true)));
+    assert_eq!(interprete.get_var_value("f"), Some(Valor::Entero(4)));
+    assert_eq!(Valor::Booleano(true).to_string(), "verdadero");
+
+    // Errores de tipo, señalando el operando equivocado
+    let error_tipo = |prog: &str| {
+        let mut interprete = Interpreter::new(prog).unwrap();
+        let mut status = TankStatus::default();
+        loop {
+            match interprete.step_inst(&mut status) {
+                Ok(StepResult::Fin) => panic!("el programa terminó sin error"),
+                Ok(_) => {}
+                Err(err) => match err.error {
+                    ErrorInterprete::TipoIncorrecto {
+                        esperado,
+                        encontrado,
+                    } => break (esperado, encontrado, err.span.col, err.span.end_col),
+                    e => panic!("{:?}", e),
+                },
+            }
+        }
+    };
+    use crate::valor::Tipo::{Booleano, Entero};
+    assert_eq!(
+        error_tipo("var x = 1 + verdadero;"),
+        (Entero, Booleano, 13, 22)
+    );
+    assert_eq!(error_tipo("si(1){ avanza; }"), (Booleano, Entero, 4, 5));
+    assert_eq!(
+        error_tipo("var x = 2; mientras(x){ }"),
+        (Booleano, Entero, 21, 22)
+    );
+    assert_eq!(
+        error_tipo("var x = 1 == falso;"),
+        (Entero, Booleano, 14, 19)
+    );
+    assert_eq!(error_tipo("var x = falso < 1;"), (Entero, Booleano, 9, 14));
+    assert_eq!(error_tipo("var x = !3;"), (Booleano, Entero, 10, 11));
+    assert_eq!(
+        error_tipo("var x = 1 && verdadero;"),
+        (Booleano, Entero, 9, 10)
+    );
+    assert_eq!(
+        error_tipo("repite falso { avanza; }"),
+        (Entero, Booleano, 8, 13)
+    );
+    assert_eq!(
+        error_tipo("funcion f() { regresa verdadero; } var x = -f();"),
+        (Entero, Booleano, 45, 48)
+    );
+
+    let mut interprete = Interpreter::new("si(1){ avanza; }").unwrap();
+    let err = interprete.step_inst(&mut status).unwrap_err();
+    assert_eq!(
+        err.error.to_string(),
+        "Se esperaba un valor lógico, pero se encontró un número"
+    );
+}
 
-    let status = interprete.step_inst(&status).unwrap();
-    let status = interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x").unwrap(), 5);
+#[test]
+fn test_errores_aritmeticos() {
+    let mut status = TankStatus::default();
+
+    let mut interprete = Interpreter::new("var y = 0;\nvar x = 1 / y;").unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    let err = interprete.step_inst(&mut status).unwrap_err();
+    assert!(matches!(err.error, ErrorInterprete::DivisionEntreCero));
+    assert_eq!((err.span.line, err.span.col, err.span.end_col), (2, 9, 14));
 
-    let status = interprete.step_inst(&status).unwrap();
-    let status = interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x").unwrap(), 9);
+    let mut interprete = Interpreter::new("var y = 0;\nsi(y / y == 1){ avanza; }").unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    let err = interprete.step_inst(&mut status).unwrap_err();
+    assert!(matches!(err.error, ErrorInterprete::DivisionEntreCero));
 
-    let status = interprete.step_inst(&status).unwrap();
-    let status = interprete.step_inst(&status).unwrap();
-    let status = interprete.step_inst(&status).unwrap();
-    assert_eq!(interprete.get_var_value("x").unwrap(), 7);
+    let mut interprete = Interpreter::new(
+        "var x = 9223372036854775807;
+         var y = x + 1;
+         y = x * 2;
+         y = 0 - x - 2;
+         y = (0 - x - 1) / (0 - 1);",
+    )
+    .unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    for _ in 0..4 {
+        let err = interprete.step_inst(&mut status).unwrap_err();
+        assert!(matches!(err.error, ErrorInterprete::Desbordamiento));
+    }
+
+    let mut interprete = Interpreter::new("var y = 0;\nvar x = 5 % y;").unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    let err = interprete.step_inst(&mut status).unwrap_err();
+    assert!(matches!(err.error, ErrorInterprete::DivisionEntreCero));
+    assert_eq!((err.span.line, err.span.col, err.span.end_col), (2, 9, 14));
+
+    let mut interprete = Interpreter::new(
+        "var x = -9223372036854775807 - 1;
+         var y = -x;
+         y = abs(x);
+         y = x % -1;",
+    )
+    .unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    for _ in 0..3 {
+        let err = interprete.step_inst(&mut status).unwrap_err();
+        assert!(matches!(err.error, ErrorInterprete::Desbordamiento));
+    }
+}
+
+#[test]
+fn test_tracer() {
+    use crate::ast::{Span, Stmt};
+    use crate::tracer::Tracer;
+    use std::sync::{Arc, Mutex};
+
+    struct Registro(Arc<Mutex<Vec<String>>>);
+
+    impl Tracer for Registro {
+        fn on_instruction(&mut self, stmt: &Stmt) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("inst {}", stmt.span.line));
+        }
+        fn on_block_enter(&mut self, tipo: TipoBloque, span: Span) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("entra {:?} {}", tipo, span.line));
+        }
+        fn on_block_exit(&mut self, tipo: TipoBloque, span: Span) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("sale {:?} {}", tipo, span.line));
+        }
+        fn on_var_change(&mut self, name: &str, value: Valor) {
+            self.0.lock().unwrap().push(format!("{} = {}", name, value));
+        }
+    }
+
+    let mut interprete = Interpreter::new(
+        "var x = 0;
+        mientras(x < 1){
+            x = x + 1;
+        }
+        avanza;",
+    )
+    .unwrap();
+    let eventos = Arc::new(Mutex::new(vec![]));
+    interprete.set_tracer(Box::new(Registro(eventos.clone())));
+
+    let mut status = TankStatus::default();
+    status.set_dir(TankDirection::South);
+    for _ in 0..5 {
+        interprete.step_inst(&mut status).unwrap();
+    }
+    assert_eq!(
+        *eventos.lock().unwrap(),
+        vec![
+            "inst 1",
+            "x = 0",
+            "inst 2",
+            "entra Mientras 2",
+            "inst 3",
+            "x = 1",
+            "sale Mientras 2",
+            "inst 5",
+            "radar = 9",
+        ]
+    );
+    assert!(interprete.take_tracer().is_some());
+}
+
+#[test]
+fn test_limites() {
+    let mut status = TankStatus::default();
+
+    // Un ciclo vacío infinito regresa el control en cada paso
+    let mut interprete = Interpreter::new("mientras(1 == 1){}").unwrap();
+    interprete.set_limites(Limites {
+        por_paso: 10,
+        total: None,
+        ..Limites::default()
+    });
+    for _ in 0..100 {
+        assert_eq!(
+            interprete.step_inst(&mut status).unwrap(),
+            StepResult::Condicion { valor: true }
+        );
+    }
+    assert_eq!(interprete.consumo(), 100);
+
+    // Si se acaba el combustible al salir de bloques, el paso se pausa
+    let mut interprete = Interpreter::new("si(1 == 1){ si(1 == 1){} } avanza;").unwrap();
+    interprete.set_limites(Limites {
+        por_paso: 1,
+        total: None,
+        ..Limites::default()
+    });
+    interprete.step_inst(&mut status).unwrap();
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Pausa
+    );
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Pausa
+    );
+    assert_eq!(
+        interprete.step_inst(&mut status).unwrap(),
+        StepResult::Accion(Accion::Avanza)
+    );
+
+    let mut interprete = Interpreter::new(
+        "var x = 0;
+        mientras(x < 100){
+            x = x + 1;
+        }",
+    )
+    .unwrap();
+    interprete.set_limites(Limites {
+        por_paso: 1000,
+        total: Some(20),
+        ..Limites::default()
+    });
+    let err = loop {
+        if let Err(err) = interprete.step_inst(&mut status) {
+            break err;
+        }
+    };
+    assert!(matches!(err.error, ErrorInterprete::LimiteExcedido));
+    assert_eq!(err.span.line, 3);
+    assert_eq!(err.traza.len(), 1);
+    assert_eq!(interprete.consumo(), 20);
+
+    // Al terminar el programa ya no se consume combustible
+    let mut interprete = Interpreter::new("avanza;").unwrap();
+    interprete.set_limites(Limites {
+        por_paso: 1,
+        total: Some(1),
+        ..Limites::default()
+    });
+    interprete.step_inst(&mut status).unwrap();
+    assert_eq!(interprete.step_inst(&mut status).unwrap(), StepResult::Fin);
+    assert_eq!(interprete.consumo(), 1);
+}
+
+#[test]
+fn test_paso_sin_recursion() {
+    // Los pasos corren en un hilo con una pila chica, donde una ejecución
+    // recursiva del programa más anidado que se puede escribir se desbordaría
+    let niveles = MAX_ANIDAMIENTO;
+    let prog = format!(
+        "{} avanza;",
+        "si(verdadero){ ".repeat(niveles) + &"} ".repeat(niveles)
+    );
+    let mut interprete = Interpreter::new(&prog).unwrap();
+    interprete.set_limites(Limites {
+        por_paso: 1_000_000,
+        total: None,
+        ..Limites::default()
+    });
+    let chica = std::thread::Builder::new().stack_size(64 << 10);
+    let hilo = chica.spawn(move || {
+        let mut status = TankStatus::default();
+        for _ in 0..niveles {
+            interprete.step_inst(&mut status).unwrap();
+        }
+        // Un solo paso sale de todos los bloques y ejecuta `avanza`
+        let paso = interprete.step_inst(&mut status).unwrap();
+        (interprete, paso)
+    });
+    let (interprete, paso) = hilo.unwrap().join().unwrap();
+    assert_eq!(paso, StepResult::Accion(Accion::Avanza));
+    assert_eq!(interprete.consumo(), 2 * niveles + 1);
 }
@@ -1,3 +1,4 @@
 #[cfg(test)]
 mod instrucciones;
-
+#[cfg(test)]
+mod mundo;
@@ -0,0 +1,537 @@
+use crate::ast::{Arma, DirGiro};
+use crate::error::{ErrorArena, ErrorInterprete};
+use crate::interprete::{Accion, Interpreter, Limites};
+use crate::mundo::{Arena, EstadoTanque, Evento};
+use crate::parser::compile;
+use crate::tablero::{Casilla, Tablero};
+use crate::tank_status::{Position, TankDirection, TankStatus};
+use crate::valor::Valor;
+
+#[test]
+fn test_turnos() {
+    let mut arena = Arena::new();
+    let a = arena
+        .add_tank(
+            "var x = 1; avanza; x = 2; gira derecha; dispara;",
+            TankStatus::new(5, 5),
+        )
+        .unwrap();
+    let b = arena
+        .add_tank("repite 2 { avanza; }", TankStatus::new(10, 0))
+        .unwrap();
+    assert_eq!((a, b), (0, 1));
+    assert!(arena.add_tank("avanza", TankStatus::default()).is_err());
+    assert_eq!(arena.tanks().len(), 2);
+
+    // Cada tanque realiza una acción por turno, en orden
+    assert_eq!(
+        arena.step_turn(),
+        vec![
+            Evento::Accion {
+                tanque: 0,
+                accion: Accion::Avanza
+            },
+            Evento::Accion {
+                tanque: 1,
+                accion: Accion::Avanza
+            },
+        ]
+    );
+    assert_eq!(arena.turn(), 1);
+    assert_eq!(arena.tank(0).unwrap().status().get_pos(), (4, 5));
+    assert_eq!(arena.tank(1).unwrap().status().get_pos(), (9, 0));
+    assert_eq!(
+        arena.tank(0).unwrap().interprete().get_var_value("x"),
+        Some(Valor::Entero(1))
+    );
+
+    assert_eq!(
+        arena.step_turn(),
+        vec![
+            Evento::Accion {
+                tanque: 0,
+                accion: Accion::Gira(DirGiro::Derecha)
+            },
+            Evento::Accion {
+                tanque: 1,
+                accion: Accion::Avanza
+            },
+        ]
+    );
+    assert_eq!(
+        arena.tank(0).unwrap().status().get_dir(),
+        TankDirection::East
+    );
+
+    assert_eq!(
+        arena.step_turn(),
+        vec![
+            Evento::Accion {
+                tanque: 0,
+                accion: Accion::Dispara(Arma::Chico)
+            },
+            Evento::Fin { tanque: 1 },
+        ]
+    );
+    assert_eq!(arena.tank(1).unwrap().estado(), EstadoTanque::Terminado);
+    assert!(!arena.terminada());
+
+    // Un tanque que terminó ya no participa
+    assert_eq!(arena.step_turn(), vec![Evento::Fin { tanque: 0 }]);
+    assert!(arena.terminada());
+    assert_eq!(arena.step_turn(), vec![]);
+}
+
+#[test]
+fn test_errores_y_pasos() {
+    let mut arena = Arena::new();
+    arena
+        .add_tank("avanza; var x = y;", TankStatus::new(5, 5))
+        .unwrap();
+    // Sin acciones, el tanque agota sus pasos en cada turno sin bloquear a los
+    // demás
+    arena
+        .add_tank(
+            "var x = 0; mientras(verdadero){ x = x + 1; }",
+            TankStatus::new(0, 0),
+        )
+        .unwrap();
+    arena.set_pasos_por_turno(10);
+
+    assert_eq!(
+        arena.step_turn(),
+        vec![Evento::Accion {
+            tanque: 0,
+            accion: Accion::Avanza
+        }]
+    );
+    assert_eq!(arena.step_turn(), vec![Evento::Error { tanque: 0 }]);
+    let tanque = arena.tank(0).unwrap();
+    assert_eq!(tanque.estado(), EstadoTanque::Error);
+    assert!(matches!(
+        tanque.error().unwrap().error,
+        ErrorInterprete::VarNoDecl(ref var) if var == "y"
+    ));
+    assert_eq!(arena.tank(1).unwrap().estado(), EstadoTanque::Activo);
+    assert!(!arena.terminada());
+    let x = arena.tank(1).unwrap().interprete().get_var_value("x");
+    assert!(matches!(x, Some(Valor::Entero(x)) if x > 0));
+
+    // Varios tanques pueden compartir un programa ya compilado, y un
+    // intérprete puede llegar configurado
+    let program = compile("gira izquierda;").unwrap();
+    let mut arena = Arena::new();
+    arena
+        .add_program(program.clone(), TankStatus::new(1, 1))
+        .unwrap();
+    arena.add_program(program, TankStatus::new(2, 2)).unwrap();
+    let mut interprete = Interpreter::new("mientras(verdadero){ }").unwrap();
+    interprete.set_limites(Limites {
+        total: Some(5),
+        ..Limites::default()
+    });
+    arena
+        .add_interpreter(interprete, TankStatus::new(3, 3))
+        .unwrap();
+    let eventos = arena.step_turn();
+    assert_eq!(eventos.len(), 3);
+    assert_eq!(eventos[2], Evento::Error { tanque: 2 });
+    assert!(arena
+        .tanks()
+        .iter()
+        .take(2)
+        .all(|tanque| tanque.status().get_dir() == TankDirection::West));
+}
+
+#[test]
+fn test_disparos() {
+    let mut arena = Arena::new();
+    arena
+        .add_tank("dispara; gira derecha; dispara;", TankStatus::new(5, 5))
+        .unwrap();
+    // El primer tanque en la línea de fuego protege a los que están detrás
+    arena
+        .add_tank("gira izquierda; avanza;", TankStatus::new(2, 5))
+        .unwrap();
+    arena.add_tank("dispara;", TankStatus::new(1, 5)).unwrap();
+
+    let dispara = |tanque| Evento::Accion {
+        tanque,
+        accion: Accion::Dispara(Arma::Chico),
+    };
+    assert_eq!(
+        arena.step_turn(),
+        vec![
+            dispara(0),
+            Evento::Impacto {
+                tanque: 0,
+                objetivo: 1
+            },
+            Evento::Accion {
+                tanque: 1,
+                accion: Accion::Gira(DirGiro::Izquierda)
+            },
+            // Hacia el norte no hay nadie
+            dispara(2),
+        ]
+    );
+    let status = |arena: &Arena, tanque| *arena.tank(tanque).unwrap().status();
+    assert!(status(&arena, 0).shot());
+    assert!(status(&arena, 1).got_shot());
+    assert_eq!(status(&arena, 1).health(), 90);
+    assert_eq!(status(&arena, 2).health(), 100);
+
+    // Las marcas se borran en el siguiente turno
+    arena.step_turn();
+    assert!(!status(&arena, 0).shot());
+    assert!(!status(&arena, 1).got_shot());
+    assert!(!status(&arena, 2).shot());
+
+    // Un disparo que llega al borde del tablero no alcanza a nadie
+    assert_eq!(
+        arena.step_turn(),
+        vec![dispara(0), Evento::Fin { tanque: 1 }]
+    );
+    assert!(arena
+        .tanks()
+        .iter()
+        .all(|tanque| !tanque.status().got_shot()));
+}
+
+#[test]
+fn test_destruido() {
+    let mut arena = Arena::new();
+    arena
+        .add_tank("repite 5 { dispara grande; }", TankStatus::new(5, 5))
+        .unwrap();
+    arena
+        .add_tank(
+            "mientras(verdadero){ gira derecha; }",
+            TankStatus::new(3, 5),
+        )
+        .unwrap();
+    arena
+        .add_tank(
+            "gira izquierda; gira izquierda; avanza; avanza;",
+            TankStatus::new(2, 5),
+        )
+        .unwrap();
+
+    for _ in 0..3 {
+        arena.step_turn();
+    }
+    assert_eq!(arena.tank(1).unwrap().status().health(), 10);
+    // El tanque que se queda sin vida deja de jugar
+    let eventos = arena.step_turn();
+    assert_eq!(
+        eventos[1..],
+        [
+            Evento::Impacto {
+                tanque: 0,
+                objetivo: 1
+            },
+            Evento::Destruido { tanque: 1 },
+            Evento::Accion {
+                tanque: 2,
+                accion: Accion::Avanza
+            },
+            Evento::Choque { tanque: 2 },
+        ]
+    );
+    let tanque = arena.tank(1).unwrap();
+    assert_eq!(tanque.estado(), EstadoTanque::Destruido);
+    assert_eq!(tanque.status().health(), 0);
+
+    // Los disparos pasan sobre sus restos, pero los tanques no
+    let eventos = arena.step_turn();
+    assert_eq!(
+        eventos[1..],
+        [
+            Evento::Impacto {
+                tanque: 0,
+                objetivo: 2
+            },
+            Evento::Fin { tanque: 2 },
+        ]
+    );
+    assert_eq!(arena.tank(2).unwrap().status().get_pos(), (2, 5));
+    assert_eq!(arena.tank(2).unwrap().status().health(), 70);
+
+    // Sin tanques activos, la partida termina
+    assert_eq!(arena.step_turn(), vec![Evento::Fin { tanque: 0 }]);
+    assert!(arena.terminada());
+    assert_eq!(arena.tank(1).unwrap().estado(), EstadoTanque::Destruido);
+}
+
+#[test]
+fn test_armas() {
+    let mut arena = Arena::new();
+    arena
+        .add_tank(
+            "dispara grande; dispara grande; dispara chico;",
+            TankStatus::new(10, 5),
+        )
+        .unwrap();
+    // El disparo grande daña más pero no llega tan lejos
+    arena.add_tank("", TankStatus::new(4, 5)).unwrap();
+
+    let eventos = arena.step_turn();
+    assert_eq!(
+        eventos[0],
+        Evento::Accion {
+            tanque: 0,
+            accion: Accion::Dispara(Arma::Grande)
+        }
+    );
+    assert_eq!(eventos.len(), 2);
+    assert_eq!(arena.tank(1).unwrap().status().health(), 100);
+    assert_eq!(arena.tank(0).unwrap().status().ammo_big(), 99);
+
+    let mut arena = Arena::new();
+    let mut status = TankStatus::new(10, 5);
+    status.set_ammo_big(1);
+    arena
+        .add_tank("dispara grande; dispara grande; dispara;", status)
+        .unwrap();
+    arena
+        .add_tank(
+            "mientras(verdadero){ gira izquierda; }",
+            TankStatus::new(7, 5),
+        )
+        .unwrap();
+    let impacto = Evento::Impacto {
+        tanque: 0,
+        objetivo: 1,
+    };
+    assert!(arena.step_turn().contains(&impacto));
+    assert_eq!(arena.tank(1).unwrap().status().health(), 70);
+
+    // Sin munición grande no hay disparo, pero el turno se gasta
+    let eventos = arena.step_turn();
+    assert_eq!(
+        eventos[0],
+        Evento::Accion {
+            tanque: 0,
+            accion: Accion::SinMunicion(Arma::Grande)
+        }
+    );
+    assert!(!eventos.contains(&impacto));
+    assert!(!arena.tank(0).unwrap().status().shot());
+
+    assert!(arena.step_turn().contains(&impacto));
+    assert_eq!(arena.tank(1).unwrap().status().health(), 60);
+    assert_eq!(
+        arena
+            .tank(0)
+            .unwrap()
+            .interprete()
+            .get_var_value("municion_chica"),
+        Some(Valor::Entero(9999))
+    );
+}
+
+#[test]
+fn test_choques() {
+    let mut arena = Arena::new();
+    arena
+        .add_tank(
+            "avanza; avanza; var a = choque; var r = radar; avanza; var b = choque;",
+            TankStatus::new(5, 5),
+        )
+        .unwrap();
+    arena
+        .add_tank("gira derecha; avanza;", TankStatus::new(3, 5))
+        .unwrap();
+    // Un tanque en la esquina choca con el borde
+    arena.add_tank("avanza;", TankStatus::new(0, 0)).unwrap();
+
+    assert_eq!(
+        arena.step_turn(),
+        vec![
+            Evento::Accion {
+                tanque: 0,
+                accion: Accion::Avanza
+            },
+            Evento::Accion {
+                tanque: 1,
+                accion: Accion::Gira(DirGiro::Derecha)
+            },
+            Evento::Accion {
+                tanque: 2,
+                accion: Accion::Avanza
+            },
+            Evento::Choque { tanque: 2 },
+        ]
+    );
+    assert!(arena.tank(2).unwrap().status().crashed());
+    assert_eq!(arena.tank(0).unwrap().status().get_pos(), (4, 5));
+
+    // La casilla de otro tanque está ocupada
+    let eventos = arena.step_turn();
+    assert_eq!(eventos[1], Evento::Choque { tanque: 0 });
+    let tanque = arena.tank(0).unwrap();
+    assert_eq!(tanque.status().get_pos(), (4, 5));
+    assert!(tanque.status().crashed());
+
+    // Cuando el otro tanque se mueve, la casilla queda libre
+    while !arena.terminada() {
+        arena.step_turn();
+    }
+    let tanque = arena.tank(0).unwrap();
+    let interprete = tanque.interprete();
+    assert_eq!(interprete.get_var_value("a"), Some(Valor::Booleano(true)));
+    // El radar corresponde a la casilla donde se quedó
+    assert_eq!(interprete.get_var_value("r"), Some(Valor::Entero(4)));
+    assert_eq!(interprete.get_var_value("b"), Some(Valor::Booleano(false)));
+    assert_eq!(tanque.status().get_pos(), (3, 5));
+}
+
+#[test]
+fn test_tablero() {
+    let tablero: Tablero = "\n\n.....#\n.....c\n\n....~".parse().unwrap();
+    let mut arena = Arena::new();
+    arena.set_tablero(tablero);
+    arena
+        .add_tank(
+            "var r1 = radar; dispara; var r2 = radar; gira izquierda; \
+             var r3 = radar; dispara; avanza; var c = choque;",
+            TankStatus::new(5, 5),
+        )
+        .unwrap();
+    // El muro detiene el disparo antes de llegar al otro tanque
+    arena
+        .add_tank(
+            "gira izquierda; gira izquierda; dispara;",
+            TankStatus::new(0, 5),
+        )
+        .unwrap();
+    // El agua protege al tanque que está detrás
+    arena.add_tank("", TankStatus::new(5, 0)).unwrap();
+
+    // El disparo destruye la caja
+    let eventos = arena.step_turn();
+    assert_eq!(
+        eventos[1],
+        Evento::CajaDestruida {
+            tanque: 0,
+            pos: (3, 5)
+        }
+    );
+    assert_eq!(arena.tablero().get((3, 5)), Some(Casilla::Libre));
+
+    // El agua detiene los disparos y no deja pasar a los tanques
+    arena.step_turn();
+    let eventos = arena.step_turn();
+    assert_eq!(
+        eventos[0],
+        Evento::Accion {
+            tanque: 0,
+            accion: Accion::Dispara(Arma::Chico)
+        }
+    );
+    assert!(!matches!(eventos[1], Evento::Impacto { .. }));
+    let eventos = arena.step_turn();
+    assert_eq!(eventos[1], Evento::Choque { tanque: 0 });
+    assert_eq!(arena.tank(0).unwrap().status().get_pos(), (5, 5));
+
+    while !arena.terminada() {
+        assert!(!arena
+            .step_turn()
+            .iter()
+            .any(|evento| matches!(evento, Evento::Impacto { .. })));
+    }
+    let interprete = arena.tank(0).unwrap().interprete();
+    assert_eq!(interprete.get_var_value("r1"), Some(Valor::Entero(1)));
+    assert_eq!(interprete.get_var_value("r2"), Some(Valor::Entero(2)));
+    assert_eq!(interprete.get_var_value("r3"), Some(Valor::Entero(0)));
+    assert_eq!(interprete.get_var_value("c"), Some(Valor::Booleano(true)));
+    assert!(arena
+        .tanks()
+        .iter()
+        .all(|tanque| tanque.status().health() == 100));
+
+    assert!(arena.set_casilla((0, 0), Casilla::Muro));
+    assert!(!arena.set_casilla((0, 11), Casilla::Muro));
+}
+
+#[test]
+fn test_casilla_inicial() {
+    let mut arena = Arena::new();
+    arena.set_tablero("#.~\n.c.".parse().unwrap());
+    arena.add_tank("avanza;", TankStatus::new(0, 1)).unwrap();
+
+    // Fuera del tablero
+    assert_eq!(
+        arena.add_tank("avanza;", TankStatus::new(3, 11)),
+        Err(ErrorArena::FueraDelTablero { pos: (3, 11) })
+    );
+    // En la casilla de otro tanque
+    let err = arena
+        .add_tank("avanza;", TankStatus::new(0, 1))
+        .unwrap_err();
+    assert_eq!(
+        err,
+        ErrorArena::CasillaOcupada {
+            pos: (0, 1),
+            tanque: 0
+        }
+    );
+    assert_eq!(err.to_string(), "la casilla (0, 1) ya es del tanque 0");
+    // Sobre un obstáculo
+    for (pos, casilla) in [
+        ((0, 0), Casilla::Muro),
+        ((1, 1), Casilla::Caja),
+        ((0, 2), Casilla::Agua),
+    ] {
+        assert_eq!(
+            arena.add_tank("avanza;", TankStatus::new(pos.0, pos.1)),
+            Err(ErrorArena::CasillaBloqueada { pos, casilla })
+        );
+    }
+    // Las mismas reglas valen para los programas ya compilados
+    let program = compile("avanza;").unwrap();
+    assert!(arena
+        .add_program(program.clone(), TankStatus::new(0, 0))
+        .is_err());
+    let interprete = Interpreter::from_program(program);
+    assert!(arena
+        .add_interpreter(interprete, TankStatus::new(11, 0))
+        .is_err());
+    assert!(matches!(
+        arena.add_tank("avanza", TankStatus::new(5, 5)),
+        Err(ErrorArena::Compilacion(_))
+    ));
+    assert_eq!(arena.tanks().len(), 1);
+}
+
+#[test]
+fn test_radar_tras_accion() {
+    let mut arena = Arena::new();
+    arena.set_tablero("\n\n\n.....c\n.......#".parse().unwrap());
+    arena
+        .add_tank(
+            "avanza; gira derecha; gira izquierda;",
+            TankStatus::new(5, 5),
+        )
+        .unwrap();
+    arena
+        .add_tank(
+            "gira derecha; gira derecha; dispara;",
+            TankStatus::new(0, 5),
+        )
+        .unwrap();
+    let radar = |arena: &Arena| arena.tank(0).unwrap().interprete().get_var_value("radar");
+
+    // El radar ya considera los obstáculos al terminar el turno
+    arena.step_turn();
+    assert_eq!(radar(&arena), Some(Valor::Entero(0)));
+    arena.step_turn();
+    assert_eq!(radar(&arena), Some(Valor::Entero(1)));
+    // La caja que destruye otro tanque deja de bloquear el radar
+    let eventos = arena.step_turn();
+    assert!(matches!(
+        eventos[2],
+        Evento::CajaDestruida { tanque: 1, .. }
+    ));
+    assert_eq!(radar(&arena), Some(Valor::Entero(4)));
+}
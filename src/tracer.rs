@@ -0,0 +1,25 @@
+use crate::ast::{Span, Stmt};
+use crate::error::TipoBloque;
+use crate::valor::Valor;
+
+/// Observador de la ejecución de un programa. El anfitrión puede instalar uno en
+/// el intérprete con `Interpreter::set_tracer` para recibir eventos estructurados
+/// en lugar de inspeccionar el estado después de cada paso. Todos los métodos
+/// tienen una implementación vacía, así que basta con sobreescribir los que
+/// interesen.
+pub trait Tracer {
+    /// Se llama justo antes de ejecutar `stmt`.
+    fn on_instruction(&mut self, _stmt: &Stmt) {}
+
+    /// Se llama al entrar a un bloque `si`, `mientras`, `para`, `repite` o al
+    /// cuerpo de una función. `span` es la región de la instrucción que abrió
+    /// el bloque o de la llamada.
+    fn on_block_enter(&mut self, _tipo: TipoBloque, _span: Span) {}
+
+    /// Se llama al salir de cualquiera de esos bloques.
+    fn on_block_exit(&mut self, _tipo: TipoBloque, _span: Span) {}
+
+    /// Se llama cada vez que una variable se declara o cambia de valor, incluida
+    /// `radar`.
+    fn on_var_change(&mut self, _name: &str, _value: Valor) {}
+}
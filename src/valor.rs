@@ -0,0 +1,54 @@
+/// Valor de una variable o del resultado de una expresión.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Valor {
+    Entero(isize),
+    Booleano(bool),
+}
+
+/// Tipo de un `Valor`, para reportar errores de tipo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tipo {
+    Entero,
+    Booleano,
+}
+
+impl Valor {
+    pub fn tipo(&self) -> Tipo {
+        match self {
+            Valor::Entero(_) => Tipo::Entero,
+            Valor::Booleano(_) => Tipo::Booleano,
+        }
+    }
+}
+
+impl From<isize> for Valor {
+    fn from(valor: isize) -> Self {
+        Valor::Entero(valor)
+    }
+}
+
+impl From<bool> for Valor {
+    fn from(valor: bool) -> Self {
+        Valor::Booleano(valor)
+    }
+}
+
+/// Se muestra como en el código fuente: `12`, `verdadero` o `falso`.
+impl std::fmt::Display for Valor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Valor::Entero(valor) => write!(f, "{}", valor),
+            Valor::Booleano(true) => f.write_str("verdadero"),
+            Valor::Booleano(false) => f.write_str("falso"),
+        }
+    }
+}
+
+impl std::fmt::Display for Tipo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tipo::Entero => f.write_str("un número"),
+            Tipo::Booleano => f.write_str("un valor lógico"),
+        }
+    }
+}
@@ -0,0 +1,570 @@
+use crate::ast::{Arma, DirGiro};
+use crate::error::{ErrorArena, ErrorCompilacion, ErrorEjecucion, ErrorTablero};
+use crate::interprete::{Accion, Interpreter, Limites, StepResult};
+use crate::mundo::{Arena, EstadoTanque, Evento};
+use crate::tablero::{Casilla, Tablero};
+use crate::tank_status::TankStatus;
+use crate::valor::Valor;
+use wasm_bindgen::prelude::*;
+
+/// Error de compilación o de ejecución tal como se entrega a JavaScript.
+#[derive(Debug, Clone)]
+#[wasm_bindgen]
+pub struct WasmError {
+    line: usize,
+    col: usize,
+    end_line: usize,
+    end_col: usize,
+    message: String,
+    rendered: String,
+}
+
+impl From<Box<ErrorCompilacion>> for WasmError {
+    fn from(e: Box<ErrorCompilacion>) -> Self {
+        WasmError {
+            line: e.span.line,
+            col: e.span.col,
+            end_line: e.span.end_line,
+            end_col: e.span.end_col,
+            rendered: e.to_string(),
+            message: e.mensaje,
+        }
+    }
+}
+
+/// Los errores de la casilla inicial no señalan ningún lugar del programa, así
+/// que su línea y columna son 0.
+impl From<ErrorArena> for WasmError {
+    fn from(e: ErrorArena) -> Self {
+        match e {
+            ErrorArena::Compilacion(e) => e.into(),
+            e => WasmError {
+                line: 0,
+                col: 0,
+                end_line: 0,
+                end_col: 0,
+                message: e.to_string(),
+                rendered: e.to_string(),
+            },
+        }
+    }
+}
+
+impl From<ErrorEjecucion> for WasmError {
+    fn from(e: ErrorEjecucion) -> Self {
+        WasmError {
+            line: e.span.line,
+            col: e.span.col,
+            end_line: e.span.end_line,
+            end_col: e.span.end_col,
+            rendered: e.to_string(),
+            message: e.error.to_string(),
+        }
+    }
+}
+
+impl From<ErrorTablero> for WasmError {
+    fn from(e: ErrorTablero) -> Self {
+        let (line, col) = e.posicion();
+        WasmError {
+            line,
+            col,
+            end_line: line,
+            end_col: col + 1,
+            message: e.to_string(),
+            rendered: e.to_string(),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl WasmError {
+    #[wasm_bindgen(getter)]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn col(&self) -> usize {
+        self.col
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn end_line(&self) -> usize {
+        self.end_line
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn end_col(&self) -> usize {
+        self.end_col
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    /// Mensaje completo, con la línea del programa y la posición señalada.
+    #[wasm_bindgen(getter)]
+    pub fn rendered(&self) -> String {
+        self.rendered.clone()
+    }
+}
+
+/// Variable visible del programa y su valor actual.
+#[derive(Debug, Clone)]
+#[wasm_bindgen]
+pub struct Variable {
+    name: String,
+    value: Valor,
+}
+
+#[wasm_bindgen]
+impl Variable {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Valor numérico; los valores lógicos se entregan como 1 o 0.
+    #[wasm_bindgen(getter)]
+    pub fn value(&self) -> isize {
+        numerico(self.value)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn is_bool(&self) -> bool {
+        matches!(self.value, Valor::Booleano(_))
+    }
+
+    /// Valor como se escribe en el programa: `12`, `verdadero` o `falso`.
+    #[wasm_bindgen(getter)]
+    pub fn text(&self) -> String {
+        self.value.to_string()
+    }
+}
+
+/// JavaScript recibe los valores lógicos como 1 o 0.
+fn numerico(valor: Valor) -> isize {
+    match valor {
+        Valor::Entero(valor) => valor,
+        Valor::Booleano(valor) => valor.into(),
+    }
+}
+
+/// Tipo de paso ejecutado, ver `StepResult`. El detalle de las asignaciones se
+/// puede consultar con `WasmInterpreter::variables`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[wasm_bindgen]
+pub enum TipoPaso {
+    Avanza,
+    GiraIzquierda,
+    GiraDerecha,
+    DisparaChico,
+    DisparaGrande,
+    /// El tanque intentó disparar sin munición.
+    SinMunicion,
+    Asignacion,
+    CondicionVerdadera,
+    CondicionFalsa,
+    Repite,
+    Llamada,
+    Regreso,
+    Salto,
+    Pausa,
+    Fin,
+}
+
+impl From<StepResult> for TipoPaso {
+    fn from(result: StepResult) -> Self {
+        match result {
+            StepResult::Accion(accion) => match accion {
+                Accion::Avanza => TipoPaso::Avanza,
+                Accion::Gira(DirGiro::Izquierda) => TipoPaso::GiraIzquierda,
+                Accion::Gira(DirGiro::Derecha) => TipoPaso::GiraDerecha,
+                Accion::Dispara(Arma::Chico) => TipoPaso::DisparaChico,
+                Accion::Dispara(Arma::Grande) => TipoPaso::DisparaGrande,
+                Accion::SinMunicion(_) => TipoPaso::SinMunicion,
+            },
+            StepResult::Asignacion { .. } => TipoPaso::Asignacion,
+            StepResult::Condicion { valor: true } => TipoPaso::CondicionVerdadera,
+            StepResult::Condicion { valor: false } => TipoPaso::CondicionFalsa,
+            StepResult::Repite { .. } => TipoPaso::Repite,
+            StepResult::Llamada { .. } => TipoPaso::Llamada,
+            StepResult::Regreso { .. } => TipoPaso::Regreso,
+            StepResult::Salto => TipoPaso::Salto,
+            StepResult::Pausa => TipoPaso::Pausa,
+            StepResult::Fin => TipoPaso::Fin,
+        }
+    }
+}
+
+/// Envoltura de `Interpreter` para usarse desde JavaScript.
+#[derive(Debug)]
+#[wasm_bindgen]
+pub struct WasmInterpreter {
+    inner: Interpreter,
+}
+
+#[wasm_bindgen]
+impl WasmInterpreter {
+    #[wasm_bindgen(constructor)]
+    pub fn new(prog: &str) -> Result<WasmInterpreter, WasmError> {
+        let inner = Interpreter::new(prog)?;
+        Ok(WasmInterpreter { inner })
+    }
+
+    /// Ejecuta un paso sobre `status`, que se modifica en su lugar.
+    pub fn step_inst(&mut self, status: &mut TankStatus) -> Result<TipoPaso, WasmError> {
+        Ok(self.inner.step_inst(status)?.into())
+    }
+
+    /// Configura el combustible por paso y, opcionalmente, para toda la ejecución.
+    pub fn set_limites(&mut self, por_paso: usize, total: Option<usize>) {
+        self.inner.set_limites(Limites {
+            por_paso,
+            total,
+            ..self.inner.limites()
+        });
+    }
+
+    /// Configura el máximo de llamadas a funciones anidadas.
+    pub fn set_profundidad(&mut self, profundidad: usize) {
+        self.inner.set_limites(Limites {
+            profundidad,
+            ..self.inner.limites()
+        });
+    }
+
+    /// Valor numérico de la variable; los valores lógicos se entregan como 1 o
+    /// 0, ver `variables` para distinguirlos.
+    pub fn get_var_value(&self, varname: &str) -> Option<isize> {
+        self.inner.get_var_value(varname).map(numerico)
+    }
+
+    pub fn variables(&self) -> Vec<Variable> {
+        self.inner
+            .variables()
+            .into_iter()
+            .map(|(name, value)| Variable { name, value })
+            .collect()
+    }
+}
+
+/// Tipo de evento de un turno de la arena, ver `Evento`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[wasm_bindgen]
+pub enum TipoEvento {
+    Avanza,
+    GiraIzquierda,
+    GiraDerecha,
+    DisparaChico,
+    DisparaGrande,
+    /// El tanque intentó disparar sin munición.
+    SinMunicion,
+    /// El disparo del tanque alcanzó a otro, ver `WasmEvento::objetivo`.
+    Impacto,
+    /// El tanque no pudo avanzar.
+    Choque,
+    /// El disparo del tanque destruyó una caja, ver `WasmEvento::casilla_i` y
+    /// `WasmEvento::casilla_j`.
+    CajaDestruida,
+    /// El tanque se quedó sin vida.
+    Destruido,
+    Fin,
+    Error,
+}
+
+/// Evento de un turno de la arena tal como se entrega a JavaScript.
+#[derive(Debug, Clone, Copy)]
+#[wasm_bindgen]
+pub struct WasmEvento {
+    tanque: usize,
+    tipo: TipoEvento,
+    objetivo: Option<usize>,
+    casilla: Option<(usize, usize)>,
+}
+
+#[wasm_bindgen]
+impl WasmEvento {
+    #[wasm_bindgen(getter)]
+    pub fn tanque(&self) -> usize {
+        self.tanque
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn tipo(&self) -> TipoEvento {
+        self.tipo
+    }
+
+    /// Tanque alcanzado, solo en los eventos `Impacto`.
+    #[wasm_bindgen(getter)]
+    pub fn objetivo(&self) -> Option<usize> {
+        self.objetivo
+    }
+
+    /// Fila de la caja destruida, solo en los eventos `CajaDestruida`.
+    #[wasm_bindgen(getter)]
+    pub fn casilla_i(&self) -> Option<usize> {
+        self.casilla.map(|(i, _)| i)
+    }
+
+    /// Columna de la caja destruida, solo en los eventos `CajaDestruida`.
+    #[wasm_bindgen(getter)]
+    pub fn casilla_j(&self) -> Option<usize> {
+        self.casilla.map(|(_, j)| j)
+    }
+}
+
+impl From<Evento> for WasmEvento {
+    fn from(evento: Evento) -> Self {
+        let mut objetivo = None;
+        let mut casilla = None;
+        let (tanque, tipo) = match evento {
+            Evento::Accion { tanque, accion } => (
+                tanque,
+                match accion {
+                    Accion::Avanza => TipoEvento::Avanza,
+                    Accion::Gira(DirGiro::Izquierda) => TipoEvento::GiraIzquierda,
+                    Accion::Gira(DirGiro::Derecha) => TipoEvento::GiraDerecha,
+                    Accion::Dispara(Arma::Chico) => TipoEvento::DisparaChico,
+                    Accion::Dispara(Arma::Grande) => TipoEvento::DisparaGrande,
+                    Accion::SinMunicion(_) => TipoEvento::SinMunicion,
+                },
+            ),
+            Evento::Impacto {
+                tanque,
+                objetivo: alcanzado,
+            } => {
+                objetivo = Some(alcanzado);
+                (tanque, TipoEvento::Impacto)
+            }
+            Evento::CajaDestruida { tanque, pos } => {
+                casilla = Some(pos);
+                (tanque, TipoEvento::CajaDestruida)
+            }
+            Evento::Choque { tanque } => (tanque, TipoEvento::Choque),
+            Evento::Destruido { tanque } => (tanque, TipoEvento::Destruido),
+            Evento::Fin { tanque } => (tanque, TipoEvento::Fin),
+            Evento::Error { tanque } => (tanque, TipoEvento::Error),
+        };
+        WasmEvento {
+            tanque,
+            tipo,
+            objetivo,
+            casilla,
+        }
+    }
+}
+
+/// Envoltura de `Arena` para usarse desde JavaScript. Los tanques se
+/// identifican por su índice.
+#[derive(Debug, Default)]
+#[wasm_bindgen]
+pub struct WasmArena {
+    inner: Arena,
+}
+
+#[wasm_bindgen]
+impl WasmArena {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmArena {
+        Self::default()
+    }
+
+    /// Agrega un tanque que ejecuta `prog`, empezando en una copia de `status`.
+    /// Falla si el programa no compila o si no puede empezar en esa casilla.
+    pub fn add_tank(&mut self, prog: &str, status: &TankStatus) -> Result<usize, WasmError> {
+        Ok(self.inner.add_tank(prog, *status)?)
+    }
+
+    pub fn step_turn(&mut self) -> Vec<WasmEvento> {
+        self.inner
+            .step_turn()
+            .into_iter()
+            .map(WasmEvento::from)
+            .collect()
+    }
+
+    pub fn set_pasos_por_turno(&mut self, pasos: usize) {
+        self.inner.set_pasos_por_turno(pasos);
+    }
+
+    /// Cambia el terreno de la partida por el que describe `texto`, ver
+    /// `Tablero`.
+    pub fn set_tablero(&mut self, texto: &str) -> Result<(), WasmError> {
+        self.inner.set_tablero(texto.parse::<Tablero>()?);
+        Ok(())
+    }
+
+    /// Cambia una casilla del tablero. Regresa `false` si está fuera del
+    /// tablero.
+    pub fn set_casilla(&mut self, i: usize, j: usize, casilla: Casilla) -> bool {
+        self.inner.set_casilla((i, j), casilla)
+    }
+
+    pub fn casilla(&self, i: usize, j: usize) -> Option<Casilla> {
+        self.inner.tablero().get((i, j))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn turn(&self) -> usize {
+        self.inner.turn()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn num_tanks(&self) -> usize {
+        self.inner.tanks().len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn terminada(&self) -> bool {
+        self.inner.terminada()
+    }
+
+    /// Copia del estado del tanque.
+    pub fn tank_status(&self, tanque: usize) -> Option<TankStatus> {
+        Some(*self.inner.tank(tanque)?.status())
+    }
+
+    pub fn estado(&self, tanque: usize) -> Option<EstadoTanque> {
+        Some(self.inner.tank(tanque)?.estado())
+    }
+
+    /// Error que detuvo el programa del tanque, si lo hubo.
+    pub fn error(&self, tanque: usize) -> Option<WasmError> {
+        Some(self.inner.tank(tanque)?.error()?.clone().into())
+    }
+
+    /// Variables visibles del programa del tanque.
+    pub fn variables(&self, tanque: usize) -> Vec<Variable> {
+        self.inner
+            .tank(tanque)
+            .map(|tanque| tanque.interprete().variables())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, value)| Variable { name, value })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tank_status::GRID_DIMMENSIONS;
+
+    #[test]
+    pub fn test_wasm_interpreter() {
+        let err = WasmInterpreter::new("var x = 1;\ngira arriba;").unwrap_err();
+        assert_eq!((err.line(), err.col()), (2, 6));
+        assert_eq!((err.end_line(), err.end_col()), (2, 12));
+        assert!(err.rendered().contains("gira arriba;"));
+
+        let mut interprete = WasmInterpreter::new("var x = 1; x = y;").unwrap();
+        let mut status = TankStatus::default();
+        assert_eq!(
+            interprete.step_inst(&mut status).unwrap(),
+            TipoPaso::Asignacion
+        );
+        assert_eq!(interprete.get_var_value("x"), Some(1));
+        let vars = interprete.variables();
+        assert_eq!(vars.len(), 5);
+        assert_eq!((vars[4].name().as_str(), vars[4].value()), ("x", 1));
+        assert!(!vars[4].is_bool());
+        let err = interprete.step_inst(&mut status).unwrap_err();
+        assert!(err.message().contains('y'));
+        assert_eq!((err.line(), err.col(), err.end_col()), (1, 16, 17));
+
+        let mut interprete = WasmInterpreter::new("var b = radar == 0;").unwrap();
+        interprete.step_inst(&mut status).unwrap();
+        assert_eq!(interprete.get_var_value("b"), Some(1));
+        let b = &interprete.variables()[0];
+        assert!(b.is_bool());
+        assert_eq!(b.text(), "verdadero");
+
+        // Un tanque fuera del tablero no detiene al intérprete
+        for mut status in [
+            TankStatus::new(GRID_DIMMENSIONS + 5, 0),
+            TankStatus::new(usize::MAX, usize::MAX),
+        ] {
+            let mut interprete =
+                WasmInterpreter::new("gira derecha; avanza; gira derecha; avanza;").unwrap();
+            for _ in 0..4 {
+                interprete.step_inst(&mut status).unwrap();
+            }
+            assert!(status.crashed());
+            assert_eq!(interprete.get_var_value("radar"), Some(0));
+        }
+    }
+
+    #[test]
+    pub fn test_wasm_arena() {
+        let mut arena = WasmArena::new();
+        assert!(arena.add_tank("avanza", &TankStatus::new(5, 5)).is_err());
+        let err = arena
+            .add_tank("avanza;", &TankStatus::new(3, 20))
+            .unwrap_err();
+        assert_eq!((err.line(), err.col()), (0, 0));
+        assert!(err.message().contains("fuera del tablero"));
+        let a = arena
+            .add_tank("avanza; gira izquierda;", &TankStatus::new(5, 5))
+            .unwrap();
+        let b = arena
+            .add_tank("var x = y;", &TankStatus::new(0, 0))
+            .unwrap();
+        assert_eq!((a, b, arena.num_tanks()), (0, 1, 2));
+
+        let eventos = arena.step_turn();
+        let eventos: Vec<_> = eventos.iter().map(|e| (e.tanque(), e.tipo())).collect();
+        assert_eq!(
+            eventos,
+            vec![(0, TipoEvento::Avanza), (1, TipoEvento::Error)]
+        );
+        assert_eq!(arena.tank_status(0).unwrap().pos_i(), 4);
+        assert_eq!(arena.estado(1), Some(EstadoTanque::Error));
+        assert!(arena.error(1).unwrap().message().contains('y'));
+        assert_eq!(arena.variables(0)[3].name(), "radar");
+
+        arena.step_turn();
+        let eventos = arena.step_turn();
+        assert_eq!(eventos[0].tipo(), TipoEvento::Fin);
+        assert!(arena.terminada());
+        assert_eq!(arena.turn(), 3);
+        assert!(arena.tank_status(2).is_none());
+
+        let mut arena = WasmArena::new();
+        arena.add_tank("dispara;", &TankStatus::new(5, 5)).unwrap();
+        arena.add_tank("avanza;", &TankStatus::new(0, 5)).unwrap();
+        let eventos = arena.step_turn();
+        let eventos: Vec<_> = eventos
+            .iter()
+            .map(|e| (e.tanque(), e.tipo(), e.objetivo()))
+            .collect();
+        assert_eq!(
+            eventos,
+            vec![
+                (0, TipoEvento::DisparaChico, None),
+                (0, TipoEvento::Impacto, Some(1)),
+                (1, TipoEvento::Avanza, None),
+                (1, TipoEvento::Choque, None),
+            ]
+        );
+        assert_eq!(arena.tank_status(1).unwrap().health(), 90);
+
+        let mut arena = WasmArena::new();
+        let err = arena.set_tablero("..\n.?").unwrap_err();
+        assert_eq!((err.line(), err.col()), (2, 2));
+        arena.set_tablero(".c").unwrap();
+        assert_eq!(arena.casilla(0, 1), Some(Casilla::Caja));
+        assert!(arena.set_casilla(0, 2, Casilla::Agua));
+        assert_eq!(arena.casilla(0, 11), None);
+        arena
+            .add_tank("gira derecha; dispara;", &TankStatus::new(0, 0))
+            .unwrap();
+        arena.step_turn();
+        let evento = arena.step_turn()[1];
+        assert_eq!(evento.tipo(), TipoEvento::CajaDestruida);
+        assert_eq!((evento.casilla_i(), evento.casilla_j()), (Some(0), Some(1)));
+        assert_eq!(arena.casilla(0, 1), Some(Casilla::Libre));
+    }
+}